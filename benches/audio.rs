@@ -0,0 +1,50 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::num::{NonZeroU32, NonZeroU8};
+use uiua::{encode::value_to_audio_channels, Value};
+use wawa::*;
+
+/// A second of a stereo sine wave, the same way it'd come off the stack
+fn stereo_clip() -> Value {
+    let mut runtime = uiua::Uiua::with_safe_sys();
+    runtime
+        .run_str("⊟.∿×τ×220÷44100⇡44100")
+        .expect("Failed to build the audio clip");
+    runtime.take_stack().pop().unwrap()
+}
+
+/// How audio used to be encoded, with fresh buffers for every clip
+fn encode_with_fresh_buffers(value: &Value) -> Box<[u8]> {
+    let channels: Vec<Vec<f32>> = value_to_audio_channels(value)
+        .unwrap()
+        .into_iter()
+        .map(|v| v.into_iter().map(|x| x as f32).collect())
+        .collect();
+    let mut sink = Vec::new();
+    let mut encoder = vorbis_rs::VorbisEncoderBuilder::new(
+        NonZeroU32::new(44100).unwrap(),
+        NonZeroU8::new(channels.len() as u8).unwrap(),
+        &mut sink,
+    )
+    .unwrap()
+    .build()
+    .unwrap();
+    encoder.encode_audio_block(channels).unwrap();
+    encoder.finish().unwrap();
+    sink.into_boxed_slice()
+}
+
+fn audio_encoding(c: &mut Criterion) {
+    let clip = stereo_clip();
+    let cfg = RunConfig::default();
+    let mut group = c.benchmark_group("audio encoding");
+    group.bench_function("reused buffers", |b| {
+        b.iter(|| value_to_outputs(clip.clone(), 0, &cfg))
+    });
+    group.bench_function("fresh buffers", |b| {
+        b.iter(|| encode_with_fresh_buffers(&clip))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, audio_encoding);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use uiua::Value;
+use wawa::*;
+
+/// A big image with enough detail that compressing it is real work
+fn large_image() -> Value {
+    let mut runtime = uiua::Uiua::with_safe_sys();
+    runtime
+        .run_str("÷2+1∿÷20⊞×.⇡1000")
+        .expect("Failed to build the image");
+    runtime.take_stack().pop().unwrap()
+}
+
+fn png_encoding(c: &mut Criterion) {
+    let image = large_image();
+    let mut group = c.benchmark_group("png encoding");
+    group.sample_size(10);
+    for (name, speed) in [("fast", PngSpeed::Fast), ("default", PngSpeed::Default)] {
+        let cfg = RunConfig {
+            png_compression: speed,
+            ..RunConfig::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| value_to_outputs(image.clone(), 0, &cfg))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, png_encoding);
+criterion_main!(benches);
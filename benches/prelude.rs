@@ -0,0 +1,26 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use wawa::*;
+
+/// A prelude with a few helpers and a table worked out up front, like a server might set
+const PRELUDE: &str = "Primes ← ▽⊸(=2/+=0⊞◿.)+1⇡1000
+Mean ← ÷⊃⧻/+
+Clamp ← ↥⊙↧
+Digits ← ⇌◿10⌊÷ⁿ⇡⌈ₙ10+1.10";
+
+fn prelude_reuse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("short run with a prelude");
+    for (name, reuse_prelude) in [("set up every run", false), ("reused", true)] {
+        let cfg = RunConfig {
+            prelude: Some(PRELUDE.into()),
+            reuse_prelude,
+            ..RunConfig::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| run_uiua_with_config("Mean ↙10 Primes", &cfg).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, prelude_reuse);
+criterion_main!(benches);
@@ -0,0 +1,26 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use wawa::*;
+
+/// Eight detailed images, so most of the time goes into encoding them rather than running
+const EIGHT_IMAGES: &str = "F ← ÷2+1∿÷20⊞×.⇡600\nF F F F F F F F";
+
+fn parallel_rendering(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rendering 8 images");
+    group.sample_size(10);
+    for (name, threads) in [
+        ("one thread", 1),
+        ("default threads", RunConfig::default().render_threads),
+    ] {
+        let cfg = RunConfig {
+            render_threads: threads,
+            ..RunConfig::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| run_uiua_with_config(EIGHT_IMAGES, &cfg).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parallel_rendering);
+criterion_main!(benches);
@@ -0,0 +1,73 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The system allocator, keeping count of how much each thread has allocated, which
+/// [`RunConfig::runaway_guard`](crate::RunConfig::runaway_guard) needs to work. Libraries can't
+/// pick the allocator, so a binary wanting the guard installs this itself:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: wawa::CountingAllocator = wawa::CountingAllocator;
+/// ```
+pub struct CountingAllocator;
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Whether anything has been allocated through [`CountingAllocator`], which means it's installed
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Bytes allocated on this thread minus bytes freed on it. Memory can be freed on a different
+    /// thread than it was allocated on, so only how much this changes by means anything.
+    static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+}
+
+fn count(change: isize) {
+    if !INSTALLED.load(Ordering::Relaxed) {
+        INSTALLED.store(true, Ordering::Relaxed);
+    }
+    // Only fails while the thread is being torn down, when nothing is guarded anymore
+    let _ = LIVE_BYTES.try_with(|live| live.set(live.get().wrapping_add(change)));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            count(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            count(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        count(-(layout.size() as isize));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            count(new_size as isize - layout.size() as isize);
+        }
+        new_ptr
+    }
+}
+
+/// How many bytes the current thread has allocated and not freed, see [`LIVE_BYTES`], or `None`
+/// if [`CountingAllocator`] isn't the program's allocator
+pub(crate) fn thread_live_bytes() -> Option<isize> {
+    INSTALLED
+        .load(Ordering::Relaxed)
+        .then(|| LIVE_BYTES.try_with(Cell::get).unwrap_or(0))
+}
@@ -0,0 +1,203 @@
+use crate::*;
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tracing::trace;
+use uiua::SysBackend;
+
+/// The system backend user code runs with. Like uiua's `SafeSys` it has no access to the host,
+/// but it also holds on to the images shown with `&ims` so they can be sent with the stack.
+pub struct BotBackend {
+    stdout: Mutex<Vec<u8>>,
+    stderr: Mutex<Vec<u8>>,
+    images: Mutex<Vec<image::DynamicImage>>,
+    /// How many media items came in after the cap was hit
+    suppressed: AtomicUsize,
+    settings: Mutex<BackendSettings>,
+}
+
+/// The parts of a [`RunConfig`] the backend goes by
+struct BackendSettings {
+    max_media: usize,
+    /// The time the clock is frozen at, see [`RunConfig::fixed_time`]
+    fixed_clock: Option<f64>,
+    /// See [`RunConfig::modules`]
+    modules: HashMap<String, String>,
+}
+
+impl BackendSettings {
+    fn new(cfg: &RunConfig) -> Self {
+        BackendSettings {
+            max_media: cfg.max_backend_media,
+            fixed_clock: cfg.fixed_time,
+            modules: cfg.modules.clone(),
+        }
+    }
+}
+
+impl BotBackend {
+    pub fn new(cfg: &RunConfig) -> Self {
+        BotBackend {
+            stdout: Mutex::default(),
+            stderr: Mutex::default(),
+            images: Mutex::default(),
+            suppressed: AtomicUsize::new(0),
+            settings: Mutex::new(BackendSettings::new(cfg)),
+        }
+    }
+
+    /// Forgets everything captured so far and starts going by `cfg`, as if it had just been made.
+    /// For reusing a backend that compiled code was already set up with.
+    pub fn reset(&self, cfg: &RunConfig) {
+        self.take_stdout();
+        self.take_stderr();
+        self.take_images();
+        self.suppressed.store(0, Ordering::Relaxed);
+        *self.settings.lock().unwrap() = BackendSettings::new(cfg);
+    }
+
+    pub fn take_stdout(&self) -> Vec<u8> {
+        std::mem::take(&mut self.stdout.lock().unwrap())
+    }
+
+    pub fn take_stderr(&self) -> Vec<u8> {
+        std::mem::take(&mut self.stderr.lock().unwrap())
+    }
+
+    /// The images shown so far, in the order they were shown
+    pub fn take_images(&self) -> Vec<image::DynamicImage> {
+        std::mem::take(&mut self.images.lock().unwrap())
+    }
+
+    /// How many media items were dropped for going over [`RunConfig::max_backend_media`]
+    pub fn suppressed_media(&self) -> usize {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+}
+
+impl SysBackend for BotBackend {
+    fn any(&self) -> &dyn Any {
+        self
+    }
+    fn any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn print_str_stdout(&self, s: &str) -> Result<(), String> {
+        self.stdout.lock().unwrap().extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+    fn print_str_stderr(&self, s: &str) -> Result<(), String> {
+        self.stderr.lock().unwrap().extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+    fn show_image(&self, image: image::DynamicImage, _label: Option<&str>) -> Result<(), String> {
+        let mut images = self.images.lock().unwrap();
+        if images.len() >= self.settings.lock().unwrap().max_media {
+            // Keep going so the code still finishes, but don't hold on to any more of them
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        trace!(
+            width = image.width(),
+            height = image.height(),
+            "Captured shown image"
+        );
+        images.push(image);
+        Ok(())
+    }
+    fn now(&self) -> f64 {
+        match self.settings.lock().unwrap().fixed_clock {
+            // uiua can't time runs with this clock then, so runs keep to the execution limit with
+            // an interrupt hook instead
+            Some(time) => time,
+            None => uiua::now(),
+        }
+    }
+    fn file_read_all(&self, path: &Path) -> Result<Vec<u8>, String> {
+        // Imports are the only thing that read files. uiua adds `.ua` to names without an
+        // extension, so the map is checked for the name either way.
+        let name = path.to_string_lossy().replace('\\', "/");
+        let name = name.trim_start_matches("./");
+        let bare = name.strip_suffix(".ua").unwrap_or(name);
+        let modules = &self.settings.lock().unwrap().modules;
+        (modules.get(name).or_else(|| modules.get(bare)))
+            .map(|source| source.clone().into_bytes())
+            .ok_or_else(|| format!("There's no module named `{bare}` to import"))
+    }
+    fn timezone(&self) -> Result<f64, String> {
+        match self.settings.lock().unwrap().fixed_clock {
+            Some(_) => Ok(0.0),
+            None => uiua::SafeSys::default().timezone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shown_images_are_captured() {
+        let outputs = run_uiua("&ims ÷99⊞+.⇡40\n1").unwrap();
+        assert!(matches!(
+            &outputs[..],
+            [OutputItem::Image(_), OutputItem::Misc(v)] if v.show() == "1"
+        ));
+    }
+
+    #[test]
+    fn clock_can_be_fixed() {
+        let cfg = RunConfig {
+            fixed_time: Some(1_700_000_000.0),
+            seed: Some(7),
+            ..Default::default()
+        };
+        let run = || eval_uiua("[now ⚂ timezone]", &cfg).unwrap()[0].show();
+        let shown = run();
+        assert!(shown.starts_with("[1700000000 "), "{shown}");
+        assert!(shown.ends_with(" 0]"), "{shown}");
+        assert_eq!(run(), shown);
+        assert_eq!(eval_uiua("now", &cfg).unwrap()[0].show(), "1700000000");
+
+        // The execution limit still applies with the clock frozen
+        let cfg = RunConfig {
+            execution_limit: std::time::Duration::from_millis(100),
+            ..cfg
+        };
+        let err = eval_uiua("⍢(+1)1 0", &cfg).unwrap_err();
+        assert!(err.contains("Maximum execution time exceeded"), "{err}");
+    }
+
+    #[test]
+    fn modules_import_from_the_map() {
+        let cfg = RunConfig {
+            modules: [("foo".into(), "Double ← ×2\n".into())].into(),
+            ..Default::default()
+        };
+        let outputs = eval_uiua("~ \"foo\" ~ Double\nDouble 21", &cfg).unwrap();
+        assert_eq!(outputs[0].show(), "42");
+
+        let err = eval_uiua("~ \"bar\" ~ Baz", &cfg).unwrap_err();
+        assert!(err.contains("no module named `bar`"), "{err}");
+    }
+
+    #[test]
+    fn media_cap_holds() {
+        let cfg = RunConfig {
+            max_backend_media: 3,
+            ..Default::default()
+        };
+        let outputs = run_uiua_with_config("⍥(&ims ÷99⊞+.⇡40)20", &cfg).unwrap();
+        let images = outputs
+            .iter()
+            .filter(|item| matches!(item, OutputItem::Image(_)))
+            .count();
+        assert_eq!(images, 3);
+        assert!(matches!(
+            outputs.last(),
+            Some(OutputItem::Note(n)) if n == "17 media outputs suppressed"
+        ));
+    }
+}
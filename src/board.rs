@@ -0,0 +1,440 @@
+use crate::*;
+use image::{imageops, Rgba, RgbaImage};
+use std::collections::HashMap;
+use uiua::Primitive;
+
+/// How many pixels each pixel of the font is drawn as
+const FONT_SCALE: u32 = 2;
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+/// Space between glyphs and between lines of text, in font pixels
+const GLYPH_GAP: u32 = 1;
+const PADDING: u32 = 6;
+/// Largest side an image gets shrunk to when placed on the board
+const THUMBNAIL_SIZE: u32 = 256;
+/// How much of a value's text gets drawn, so one huge array can't make the board enormous
+const MAX_TEXT_LINES: usize = 24;
+const MAX_TEXT_COLUMNS: usize = 80;
+
+const BACKGROUND: Rgba<u8> = Rgba([0x20, 0x22, 0x25, 0xff]);
+const LABEL_COLOR: Rgba<u8> = Rgba([0xf0, 0xa0, 0xd0, 0xff]);
+const TEXT_COLOR: Rgba<u8> = Rgba([0xe0, 0xe0, 0xe0, 0xff]);
+
+/// A tiny bundled bitmap font. Each glyph is five rows of three pixels, most significant bit on
+/// the left. uiua's glyphs aren't in it, they're drawn from the pictures [`glyph_image`] uses.
+const FONT: &[(char, [u8; 5])] = &[
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b110, 0b001, 0b010, 0b100, 0b111]),
+    ('3', [0b110, 0b001, 0b010, 0b001, 0b110]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b110, 0b001, 0b110]),
+    ('6', [0b011, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b110]),
+    // Too short for descenders, so letters with one sit a little higher
+    ('a', [0b000, 0b011, 0b101, 0b101, 0b011]),
+    ('b', [0b100, 0b110, 0b101, 0b101, 0b110]),
+    ('c', [0b000, 0b011, 0b100, 0b100, 0b011]),
+    ('d', [0b001, 0b011, 0b101, 0b101, 0b011]),
+    ('e', [0b000, 0b010, 0b111, 0b100, 0b011]),
+    ('f', [0b001, 0b010, 0b111, 0b010, 0b010]),
+    ('g', [0b000, 0b011, 0b101, 0b011, 0b110]),
+    ('h', [0b100, 0b110, 0b101, 0b101, 0b101]),
+    ('i', [0b010, 0b000, 0b010, 0b010, 0b010]),
+    ('j', [0b001, 0b000, 0b001, 0b101, 0b010]),
+    ('k', [0b100, 0b101, 0b110, 0b110, 0b101]),
+    ('l', [0b110, 0b010, 0b010, 0b010, 0b011]),
+    ('m', [0b000, 0b110, 0b111, 0b101, 0b101]),
+    ('n', [0b000, 0b110, 0b101, 0b101, 0b101]),
+    ('o', [0b000, 0b010, 0b101, 0b101, 0b010]),
+    ('p', [0b000, 0b110, 0b101, 0b110, 0b100]),
+    ('q', [0b000, 0b011, 0b101, 0b011, 0b001]),
+    ('r', [0b000, 0b101, 0b110, 0b100, 0b100]),
+    ('s', [0b000, 0b011, 0b110, 0b011, 0b110]),
+    ('t', [0b010, 0b111, 0b010, 0b010, 0b001]),
+    ('u', [0b000, 0b101, 0b101, 0b101, 0b011]),
+    ('v', [0b000, 0b101, 0b101, 0b101, 0b010]),
+    ('w', [0b000, 0b101, 0b101, 0b111, 0b101]),
+    ('x', [0b000, 0b101, 0b010, 0b010, 0b101]),
+    ('y', [0b000, 0b101, 0b101, 0b011, 0b110]),
+    ('z', [0b000, 0b111, 0b011, 0b110, 0b111]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('[', [0b110, 0b100, 0b100, 0b100, 0b110]),
+    (']', [0b011, 0b001, 0b001, 0b001, 0b011]),
+    ('(', [0b010, 0b100, 0b100, 0b100, 0b010]),
+    (')', [0b010, 0b001, 0b001, 0b001, 0b010]),
+    ('<', [0b001, 0b010, 0b100, 0b010, 0b001]),
+    ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('?', [0b110, 0b001, 0b010, 0b000, 0b010]),
+    ('"', [0b101, 0b101, 0b000, 0b000, 0b000]),
+    ('\'', [0b010, 0b010, 0b000, 0b000, 0b000]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+    ('|', [0b010, 0b010, 0b010, 0b010, 0b010]),
+    ('×', [0b000, 0b101, 0b010, 0b101, 0b000]),
+    ('¯', [0b111, 0b000, 0b000, 0b000, 0b000]),
+    ('…', [0b000, 0b000, 0b000, 0b000, 0b101]),
+    // The box drawing uiua frames arrays with
+    ('─', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('│', [0b010, 0b010, 0b010, 0b010, 0b010]),
+    ('╷', [0b000, 0b000, 0b010, 0b010, 0b010]),
+    ('╵', [0b010, 0b010, 0b010, 0b000, 0b000]),
+    ('╭', [0b000, 0b000, 0b011, 0b010, 0b010]),
+    ('╮', [0b000, 0b000, 0b110, 0b010, 0b010]),
+    ('╰', [0b010, 0b010, 0b011, 0b000, 0b000]),
+    ('╯', [0b010, 0b010, 0b110, 0b000, 0b000]),
+];
+
+/// How a character gets drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Glyph {
+    /// With the bundled font
+    Bits([u8; 5]),
+    /// With the picture of the primitive's glyph
+    Picture(Primitive),
+}
+
+/// How to draw the character, or `None` if it can't be
+fn glyph(c: char) -> Option<Glyph> {
+    if let Some(&(_, rows)) = FONT.iter().find(|(g, _)| *g == c) {
+        return Some(Glyph::Bits(rows));
+    }
+    Primitive::from_glyph(c)
+        .filter(has_glyph_picture)
+        .map(Glyph::Picture)
+}
+
+/// The text with every character that can't be drawn replaced by `?`
+fn drawable(text: &str) -> String {
+    (text.chars())
+        .map(|c| if glyph(c).is_some() { c } else { '?' })
+        .collect()
+}
+
+/// Multiplies a size by the render scale, without letting it vanish
+fn scaled(size: u32, scale: f32) -> u32 {
+    ((size as f32 * scale).round() as u32).max(1)
+}
+
+/// Draws lines of text with the bundled font, each font pixel being `pixel` pixels wide. uiua's
+/// glyphs fill the whole cell they're in. Returns `None` if any character can't be drawn.
+fn render_text(lines: &[String], color: Rgba<u8>, pixel: u32) -> Option<RgbaImage> {
+    let cell_width = (GLYPH_WIDTH + GLYPH_GAP) * pixel;
+    let cell_height = (GLYPH_HEIGHT + GLYPH_GAP) * pixel;
+    let columns = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u32;
+    let mut canvas = RgbaImage::from_pixel(
+        (columns * cell_width).max(1),
+        (lines.len() as u32 * cell_height).max(1),
+        BACKGROUND,
+    );
+    // Decoding a glyph's picture is slow, and the same few tend to come up again and again
+    let mut pictures = HashMap::new();
+    for (row, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let bits = match glyph(c)? {
+                Glyph::Bits(bits) => bits,
+                Glyph::Picture(prim) => {
+                    let picture = pictures
+                        .entry(prim)
+                        .or_insert_with(|| glyph_picture(&prim, cell_width));
+                    let top = row as u32 * cell_height + (cell_height - cell_width) / 2;
+                    let left = column as u32 * cell_width;
+                    imageops::overlay(&mut canvas, picture.as_ref()?, left as i64, top as i64);
+                    continue;
+                }
+            };
+            for (y, bits) in bits.into_iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - x)) == 0 {
+                        continue;
+                    }
+                    let left = column as u32 * cell_width + x * pixel;
+                    let top = row as u32 * cell_height + y as u32 * pixel;
+                    for dy in 0..pixel {
+                        for dx in 0..pixel {
+                            canvas.put_pixel(left + dx, top + dy, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Some(canvas)
+}
+
+/// Draws the title of a block, prefixed with the pending position label if there is one
+fn titled(kind: &str, label: &mut String, pixel: u32) -> Option<RgbaImage> {
+    let title = match std::mem::take(label) {
+        label if label.is_empty() => kind.to_string(),
+        label => format!("{label} {kind}"),
+    };
+    render_text(&[title], LABEL_COLOR, pixel)
+}
+
+/// Lays the items out one below the other on a single PNG, each under a label saying what it is.
+/// Arrays are drawn as their text grid, images as thumbnails, and audio and GIFs only get their
+/// label.
+/// Returns `None` if there's nothing to draw, some text has characters that can't be drawn, or
+/// the board couldn't be encoded, so the items can be sent as they are instead.
+pub fn render_stack_board(items: &[OutputItem]) -> Option<Vec<u8>> {
+    render_stack_board_with_config(items, &RunConfig::default())
+}
+
+/// Same as [`render_stack_board`], with arrays formatted the way the config says and text, images
+/// and spacing all made [`RunConfig::render_scale`] times bigger
+pub fn render_stack_board_with_config(items: &[OutputItem], cfg: &RunConfig) -> Option<Vec<u8>> {
+    let scale = cfg.render_scale;
+    let pixel = scaled(FONT_SCALE, scale);
+    let padding = scaled(PADDING, scale);
+    let mut blocks = Vec::new();
+    let mut label = String::new();
+    for item in items {
+        match item {
+            OutputItem::Label(text) => label = text.clone(),
+            OutputItem::Misc(value) => {
+                blocks.push(titled("value", &mut label, pixel)?);
+                let lines: Vec<String> = format_value(value, cfg)
+                    .lines()
+                    .take(MAX_TEXT_LINES)
+                    .map(|line| line.chars().take(MAX_TEXT_COLUMNS).collect())
+                    .collect();
+                blocks.push(render_text(&lines, TEXT_COLOR, pixel)?);
+            }
+            OutputItem::Image(bytes) => {
+                blocks.push(titled("image", &mut label, pixel)?);
+                let image = image::load_from_memory(bytes).ok()?;
+                let largest = scaled(THUMBNAIL_SIZE, scale);
+                let image = image.resize(
+                    scaled(image.width(), scale).min(largest),
+                    scaled(image.height(), scale).min(largest),
+                    imageops::FilterType::Nearest,
+                );
+                blocks.push(image.to_rgba8());
+            }
+            OutputItem::Audio(_) => blocks.push(titled("audio", &mut label, pixel)?),
+            OutputItem::Gif(_) => blocks.push(titled("gif", &mut label, pixel)?),
+            OutputItem::Note(note) => {
+                let lines: Vec<String> = note.lines().map(str::to_string).collect();
+                blocks.push(render_text(&lines, TEXT_COLOR, pixel)?);
+            }
+            OutputItem::Continuation(more, end) => {
+                let text = render_continuation(*more, *end, cfg);
+                blocks.push(render_text(&[text], TEXT_COLOR, pixel)?)
+            }
+        }
+    }
+    if blocks.is_empty() {
+        return None;
+    }
+
+    let width = blocks.iter().map(RgbaImage::width).max()? + 2 * padding;
+    let height = blocks.iter().map(|b| b.height() + padding).sum::<u32>() + padding;
+    let mut board = RgbaImage::from_pixel(width, height, BACKGROUND);
+    let mut top = padding;
+    for block in &blocks {
+        imageops::overlay(&mut board, block, padding as i64, top as i64);
+        top += block.height() + padding;
+    }
+    uiua::encode::image_to_bytes(&board.into(), image::ImageOutputFormat::Png).ok()
+}
+
+/// Splits the text into lines of at most `columns` characters, breaking between words where it can
+fn wrap_words(text: &str, columns: usize) -> Vec<String> {
+    let columns = columns.max(1);
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        // Words too long for a line of their own get broken up
+        while word.len() > columns {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            lines.push(word.drain(..columns).collect());
+        }
+        let word: String = word.into_iter().collect();
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > columns {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Draws a `width`×`height` reference card for the primitive: its glyph on the left, then its
+/// name, signature and short description, all in the bundled font. Text that doesn't fit is cut
+/// off, and characters that can't be drawn are shown as `?`. Returns `None` if the card has no area or couldn't be encoded.
+pub fn primitive_card(prim: &Primitive, cfg: &CardConfig) -> Option<Vec<u8>> {
+    if cfg.width == 0 || cfg.height == 0 {
+        return None;
+    }
+    let mut card = RgbaImage::from_pixel(cfg.width, cfg.height, BACKGROUND);
+
+    let glyph_size = cfg.height.saturating_sub(2 * PADDING);
+    let mut left = PADDING;
+    if let Some(glyph) = (glyph_size > 0)
+        .then(|| glyph_image(prim, glyph_size))
+        .flatten()
+        .and_then(|png| image::load_from_memory(&png).ok())
+    {
+        imageops::overlay(&mut card, &glyph.to_rgba8(), left as i64, PADDING as i64);
+        left += glyph_size + PADDING;
+    }
+
+    let name_pixel = FONT_SCALE + 1;
+    let name = render_text(&[drawable(prim.name())], LABEL_COLOR, name_pixel)?;
+    imageops::overlay(&mut card, &name, left as i64, PADDING as i64);
+    let mut top = PADDING + name.height() + PADDING;
+
+    let mut details = Vec::new();
+    if let Some(sig) = prim.signature() {
+        details.push(format!("|{}.{}", sig.args, sig.outputs));
+    }
+    if let Some(functions) = prim.modifier_args() {
+        let s = if functions == 1 { "" } else { "s" };
+        details.push(format!("modifier of {functions} function{s}"));
+    }
+    let cell_width = (GLYPH_WIDTH + GLYPH_GAP) * FONT_SCALE;
+    let cell_height = (GLYPH_HEIGHT + GLYPH_GAP) * FONT_SCALE;
+    if !details.is_empty() {
+        let details = render_text(&[drawable(&details.join("  "))], TEXT_COLOR, FONT_SCALE)?;
+        imageops::overlay(&mut card, &details, left as i64, top as i64);
+        top += details.height() + PADDING;
+    }
+
+    let columns = cfg.width.saturating_sub(left + PADDING) / cell_width;
+    let rows = cfg.height.saturating_sub(top + PADDING) / cell_height;
+    let description = primitive_short(prim.name()).unwrap_or_default();
+    let lines: Vec<String> = wrap_words(&description, columns as usize)
+        .iter()
+        .take(rows as usize)
+        .map(|line| drawable(line))
+        .collect();
+    if !lines.is_empty() {
+        let description = render_text(&lines, TEXT_COLOR, FONT_SCALE)?;
+        imageops::overlay(&mut card, &description, left as i64, top as i64);
+    }
+    uiua::encode::image_to_bytes(&card.into(), image::ImageOutputFormat::Png).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_stacks_every_item() {
+        let items = run_uiua("÷99⊞+.⇡50 \"hi\" ⇡3").unwrap();
+        let board = render_stack_board(&items).unwrap();
+        let board = image::load_from_memory(&board).unwrap();
+        // The image alone is 50 pixels tall, and there are three labels plus two arrays on top
+        assert!(board.height() > 50 + 5 * GLYPH_HEIGHT * FONT_SCALE);
+        assert!(board.width() >= 50);
+        assert_eq!(render_stack_board(&[]), None);
+    }
+
+    #[test]
+    fn board_scales() {
+        let items = run_uiua("÷99⊞+.⇡50 \"hi\"").unwrap();
+        let size = |scale| {
+            let cfg = RunConfig {
+                render_scale: scale,
+                ..RunConfig::default()
+            };
+            let board = render_stack_board_with_config(&items, &cfg).unwrap();
+            let board = image::load_from_memory(&board).unwrap();
+            (board.width() as f32, board.height() as f32)
+        };
+        let (width, height) = size(1.0);
+        let (double_width, double_height) = size(2.0);
+        assert!((double_width / width - 2.0).abs() < 0.1);
+        assert!((double_height / height - 2.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn cards_are_the_configured_size() {
+        let cfg = CardConfig {
+            width: 300,
+            height: 80,
+        };
+        let card = primitive_card(&Primitive::Reduce, &cfg).unwrap();
+        let card = image::load_from_memory(&card).unwrap().to_rgba8();
+        assert_eq!(card.dimensions(), (300, 80));
+        assert!(card.pixels().any(|pixel| *pixel == LABEL_COLOR));
+        assert!(card.pixels().any(|pixel| *pixel == TEXT_COLOR));
+
+        let empty = CardConfig { width: 0, ..cfg };
+        assert_eq!(primitive_card(&Primitive::Reduce, &empty), None);
+    }
+
+    #[test]
+    fn long_text_wraps_between_words() {
+        assert_eq!(
+            wrap_words("apply a reducing function", 10),
+            ["apply a", "reducing", "function"]
+        );
+        assert_eq!(wrap_words("abcdefgh", 3), ["abc", "def", "gh"]);
+    }
+
+    #[test]
+    fn glyphs_come_from_the_font_or_pictures() {
+        assert!(matches!(glyph('a'), Some(Glyph::Bits(_))));
+        assert_ne!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('⍥'), Some(Glyph::Picture(Primitive::Repeat)));
+        assert_eq!(glyph('€'), None);
+        assert_eq!(drawable("⍥€x"), "⍥?x");
+    }
+
+    #[test]
+    fn board_falls_back_on_undrawable_text() {
+        let glyphs = run_uiua("\"⍥/+\"").unwrap();
+        assert!(render_stack_board(&glyphs).is_some());
+        let cfg = RunConfig {
+            stack_board: true,
+            ..RunConfig::default()
+        };
+        let outputs = run_uiua_with_config("\"€5\"", &cfg).unwrap();
+        assert!(matches!(&outputs[..], [OutputItem::Misc(_)]), "{outputs:?}");
+    }
+}
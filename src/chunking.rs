@@ -0,0 +1,111 @@
+const FENCE: &str = "```";
+
+fn char_len(s: &str) -> usize {
+    s.chars().count()
+}
+
+/// Splits a message into pieces of at most `limit` characters, breaking only between lines.
+/// A code block that gets split is closed at the end of one piece and reopened, with the same
+/// language tag, at the start of the next, so every piece renders on its own. Lines that can't
+/// fit in a piece even by themselves are the one exception, and get cut wherever the limit falls.
+pub fn chunk_message(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    // Whether `current` has had anything put in it, since a blank line leaves it empty
+    let mut started = false;
+    // The line that opened the code block we're in, if any
+    let mut fence: Option<&str> = None;
+
+    for line in text.lines() {
+        let toggles = line.trim_start().starts_with(FENCE);
+        let fence_after = match (toggles, fence) {
+            (true, Some(_)) => None,
+            (true, None) => Some(line),
+            (false, fence) => fence,
+        };
+        // Always leave room to close the block, in case the next line doesn't fit
+        let closing = if fence_after.is_some() {
+            char_len(FENCE) + 1
+        } else {
+            0
+        };
+        let reopening = fence_after.map_or(0, |open| char_len(open) + 1);
+        let room = limit.saturating_sub(reopening + closing).max(1);
+
+        let chars: Vec<char> = line.chars().collect();
+        let pieces: Vec<String> = if chars.is_empty() {
+            vec![String::new()]
+        } else {
+            chars
+                .chunks(room)
+                .map(|piece| piece.iter().collect())
+                .collect()
+        };
+        for piece in pieces {
+            let needed = char_len(&current) + 1 + char_len(&piece) + closing;
+            if started && needed > limit {
+                if fence.is_some() {
+                    current.push('\n');
+                    current.push_str(FENCE);
+                }
+                chunks.push(std::mem::take(&mut current));
+                started = false;
+                if let Some(open) = fence {
+                    current.push_str(open);
+                    started = true;
+                }
+            }
+            if started {
+                current.push('\n');
+            }
+            current.push_str(&piece);
+            started = true;
+        }
+        fence = fence_after;
+    }
+
+    if started {
+        if fence.is_some() {
+            current.push('\n');
+            current.push_str(FENCE);
+        }
+        chunks.push(current);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_messages_stay_whole() {
+        assert_eq!(chunk_message("a\nb", 100), ["a\nb"]);
+        assert_eq!(chunk_message("\na\n\nb", 100), ["\na\n\nb"]);
+    }
+
+    #[test]
+    fn splits_between_lines() {
+        let text = "aaaa\nbbbb\ncccc";
+        assert_eq!(chunk_message(text, 9), ["aaaa\nbbbb", "cccc"]);
+    }
+
+    #[test]
+    fn reopens_code_blocks() {
+        let text = "intro\n```ansi\n1111\n2222\n3333\n```\noutro";
+        let chunks = chunk_message(text, 20);
+        assert!(chunks.iter().all(|c| char_len(c) <= 20), "{chunks:?}");
+        for chunk in &chunks {
+            assert_eq!(chunk.matches(FENCE).count() % 2, 0, "unbalanced {chunk:?}");
+        }
+        assert!(chunks[1].starts_with("```ansi\n"), "{chunks:?}");
+        let joined = chunks.join("\n").replace("```\n```ansi\n", "");
+        assert_eq!(joined, text);
+    }
+
+    #[test]
+    fn cuts_lines_too_long_to_fit() {
+        let chunks = chunk_message(&"x".repeat(25), 10);
+        assert_eq!(chunks, ["x".repeat(10), "x".repeat(10), "x".repeat(5)]);
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Box nesting depth at which uiua's own grid formatter gives up, same as on the pad
+const DEFAULT_MAX_DEPTH: usize = 100;
+const DEFAULT_EXECUTION_LIMIT: Duration = Duration::from_secs(2);
+/// Generous, so it only cuts off values that would hold the reply up for far too long
+const DEFAULT_RENDER_TIMEOUT: Duration = Duration::from_secs(5);
+/// Enough to keep a few cores busy encoding without a flood of images making a thread each
+const DEFAULT_RENDER_THREADS: usize = 4;
+/// Roughly how many characters fit on one line of a code block in the desktop client
+const DEFAULT_MAX_DISPLAY_COLUMNS: usize = 80;
+/// How many images code may show through the backend, so a loop can't flood the channel
+const DEFAULT_MAX_BACKEND_MEDIA: usize = 10;
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+/// About 5 seconds of animation at [`GIF_FRAME_RATE`]
+const DEFAULT_MAX_GIF_FRAMES: usize = 100;
+/// Discord's attachment size limit for unboosted servers
+const DEFAULT_MAX_IMAGE_BYTES: usize = 8 * 1024 * 1024;
+
+/// The text shown in place of hidden stack values
+pub const DEFAULT_CONTINUATION_TEMPLATE: &str = "<{n} more item{s} at the {end} of the stack>";
+
+/// What to do with values too wide to display in full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HugeValueMode {
+    /// Cut the middle out of each line
+    #[default]
+    Truncate,
+    /// Show statistics and a sparkline instead, if the value is numeric
+    Summary,
+}
+
+/// What to do with numeric arrays shaped like images but too small to be shown as one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SmallImageMode {
+    /// Display them like any other array
+    #[default]
+    Misc,
+    /// Blow them up with nearest-neighbor scaling until they're big enough to see
+    Upscale,
+    /// Draw them with characters of increasing brightness
+    AsciiArt,
+}
+
+/// Which end of the stack is shown when it has more values than fit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TruncateKeep {
+    /// Show the values pushed first, leaving out the ones on top
+    #[default]
+    Bottom,
+    /// Show the values on top, leaving out the ones pushed first
+    Top,
+}
+
+/// What to do with audio that has more channels than [`RunConfig::max_audio_channels`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelOverflow {
+    /// Average the extra channels into the allowed ones, alternating between them
+    #[default]
+    Downmix,
+    /// Display the value as an array, noting why it wasn't played
+    Decline,
+}
+
+/// How hard to compress PNGs, which for big images takes longer than running the code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PngSpeed {
+    /// Quicker to encode, but bigger
+    Fast,
+    #[default]
+    Default,
+    /// Smallest, but slowest to encode
+    Best,
+}
+
+/// Knobs controlling how code is run and how its results are displayed
+#[derive(Debug, Clone)]
+pub struct RunConfig {
+    /// How long code may run before being interrupted
+    pub execution_limit: Duration,
+    /// How many levels of nested boxes are displayed before being replaced with `…`
+    pub max_depth: usize,
+    /// How many characters wide a line of a displayed value may get before its middle is elided
+    pub max_display_columns: usize,
+    /// How values wider than [`RunConfig::max_display_columns`] are shown
+    pub huge_value_mode: HugeValueMode,
+    /// Whether runs of equal stack values are shown once with a count
+    pub collapse_duplicates: bool,
+    /// Code run before the user's, so its bindings can be used without pasting them in
+    pub prelude: Option<String>,
+    /// Whether the prelude is compiled and run only once, each run starting from a copy of the
+    /// result. What the prelude prints, or works out from the time or randomness, then comes from
+    /// the first run it was set up for.
+    pub reuse_prelude: bool,
+    /// Whether each value is preceded by a label saying where on the stack it was
+    pub label_stack_positions: bool,
+    /// Whether audio is followed by a plot of its waveform
+    pub render_waveform: bool,
+    /// Whether audio is followed by a small strip of its loudness over time, like a voice message
+    pub audio_thumbnail: bool,
+    /// How many media items code may emit through the system backend before the rest are dropped
+    pub max_backend_media: usize,
+    /// Size an encoded image may reach before it gets downscaled to fit
+    pub max_image_bytes: usize,
+    /// How many bytes all the media in one reply may add up to, counted in the order it's sent.
+    /// Media past the point it's used up is left out, and isn't encoded if it can be helped.
+    pub max_total_media_bytes: usize,
+    /// How image-shaped values smaller than the automatic image size are shown
+    pub small_image_mode: SmallImageMode,
+    /// Text shown for stack values that didn't fit, where `{n}` is how many there were, `{s}` is
+    /// an `s` unless there was only one, and `{end}` is the end of the stack they were cut from.
+    /// See [`check_continuation_template`].
+    pub continuation_template: String,
+    /// Which end of the stack is shown when not every value fits
+    pub truncate_keep: TruncateKeep,
+    /// Whether everything a run outputs gets combined into one image, see [`render_stack_board`]
+    pub stack_board: bool,
+    /// Whether text made only of whitespace is left out instead of showing up as a blank value
+    pub drop_blank_outputs: bool,
+    /// How much bigger than normal text and grids drawn into images are, for reading on small screens
+    pub render_scale: f32,
+    /// What to seed the random number generator with, so random code gives the same result each run
+    pub seed: Option<u64>,
+    /// How many characters a string inside a box array may show before the rest is elided
+    pub max_element_chars: Option<usize>,
+    /// Put between each group of three digits in numbers with more than four before the point,
+    /// like a thin space for `12 345`. Columns of numeric arrays are padded to stay aligned.
+    pub digit_group_separator: Option<char>,
+    /// How much effort goes into compressing images
+    pub png_compression: PngSpeed,
+    /// Samples per second audio is played back at, uiua's own default being 44100
+    pub sample_rate: u32,
+    /// Whether numeric lists and matrices with few rows are sent as audio, rather than shown as
+    /// arrays
+    pub audio_output: bool,
+    /// Whether numeric lists and matrices are written as LaTeX instead of uiua's grid
+    pub latex_output: bool,
+    /// Whether all the audio on the stack is joined into a single clip
+    pub concat_audio: bool,
+    /// How long turning a single value into something to send may take before it's given up on
+    pub render_timeout: Duration,
+    /// How many values are turned into something to send at the same time
+    pub render_threads: usize,
+    /// Whether a boxed string on its own is shown as just the string, without the box around it
+    pub unwrap_single_box_strings: bool,
+    /// Seconds since the Unix epoch that `now` always gives instead of the real time, in UTC.
+    /// Together with [`RunConfig::seed`] this makes code reading the time or random numbers
+    /// give the same result each run.
+    pub fixed_time: Option<f64>,
+    /// Whether arrays of frames are sent as a GIF, the way uiua shows animations. Each frame is
+    /// an image at least as big as an automatic one.
+    pub gif_output: bool,
+    /// How many frames of an animation are kept, see [`cap_animation_frames`]
+    pub max_gif_frames: usize,
+    /// Whether the top value is shown as an image even when it's too small to be one, upscaled
+    /// as with [`SmallImageMode::Upscale`]. Only matters when `small_image_mode` is `Misc`.
+    pub force_render_top_media: bool,
+    /// Whether values shown as text start with their type, like `(num) 5`
+    pub annotate_types: bool,
+    /// Whether the text of a reply is hidden behind spoilers, see [`spoiler_markdown`]. Attachments
+    /// can't be, so replies with media say they aren't hidden.
+    pub spoiler_output: bool,
+    /// Whether numeric matrices are drawn as a heatmap instead, see [`render_heatmap`]
+    pub heatmap: bool,
+    /// How many bytes code may allocate while it runs before the run is stopped, to catch code
+    /// allocating without end before the time limit does. Only what's allocated on the thread
+    /// the code runs on counts, so other runs at the same time don't add to it. Counting needs
+    /// the `counting-allocator` feature and `CountingAllocator` as the global allocator; without
+    /// them the guard is off, and a warning is logged.
+    pub runaway_guard: Option<usize>,
+    /// Source of the modules code can import with `~ "name"`, by name. There's no file system to
+    /// import from otherwise.
+    pub modules: HashMap<String, String>,
+    /// Most channels audio is sent with, as players rarely handle more than stereo
+    pub max_audio_channels: usize,
+    /// What happens to audio with more channels than that
+    pub channel_overflow: ChannelOverflow,
+    /// Whether a note is added after the outputs for each deprecated primitive the code uses. Off
+    /// by default, the bot turns it on.
+    pub deprecation_warnings: bool,
+    /// Whether only the top stack value is shown, the rest being dropped without encoding them
+    pub top_only: bool,
+    /// With `top_only`, whether a continuation says how many values were dropped
+    pub count_dropped: bool,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        RunConfig {
+            execution_limit: DEFAULT_EXECUTION_LIMIT,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_display_columns: DEFAULT_MAX_DISPLAY_COLUMNS,
+            huge_value_mode: HugeValueMode::default(),
+            collapse_duplicates: false,
+            prelude: None,
+            reuse_prelude: false,
+            label_stack_positions: false,
+            render_waveform: false,
+            audio_thumbnail: false,
+            max_backend_media: DEFAULT_MAX_BACKEND_MEDIA,
+            max_image_bytes: DEFAULT_MAX_IMAGE_BYTES,
+            // Discord applies its limit to the whole message, not just each attachment
+            max_total_media_bytes: DEFAULT_MAX_IMAGE_BYTES,
+            small_image_mode: SmallImageMode::default(),
+            continuation_template: DEFAULT_CONTINUATION_TEMPLATE.into(),
+            truncate_keep: TruncateKeep::default(),
+            stack_board: false,
+            drop_blank_outputs: false,
+            render_scale: 1.0,
+            seed: None,
+            max_element_chars: None,
+            digit_group_separator: None,
+            png_compression: PngSpeed::default(),
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            audio_output: true,
+            latex_output: false,
+            concat_audio: false,
+            render_timeout: DEFAULT_RENDER_TIMEOUT,
+            render_threads: DEFAULT_RENDER_THREADS,
+            unwrap_single_box_strings: false,
+            fixed_time: None,
+            gif_output: false,
+            max_gif_frames: DEFAULT_MAX_GIF_FRAMES,
+            force_render_top_media: false,
+            annotate_types: false,
+            spoiler_output: false,
+            heatmap: false,
+            runaway_guard: None,
+            modules: HashMap::new(),
+            max_audio_channels: 2,
+            channel_overflow: ChannelOverflow::default(),
+            deprecation_warnings: false,
+            top_only: false,
+            count_dropped: false,
+        }
+    }
+}
+
+/// What [`run_and_present`] puts alongside the outputs
+#[derive(Debug, Clone)]
+pub struct PresentConfig {
+    /// Whether the highlighted code is included, to show above the outputs
+    pub echo_source: bool,
+    /// Whether a link opening the code on the pad is included
+    pub include_pad_link: bool,
+    pub run: RunConfig,
+}
+
+impl Default for PresentConfig {
+    fn default() -> Self {
+        PresentConfig {
+            echo_source: true,
+            include_pad_link: false,
+            run: RunConfig::default(),
+        }
+    }
+}
+
+/// How [`primitive_card`] draws its card
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CardConfig {
+    /// Size of the card in pixels, which the text is wrapped and cut to fit
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for CardConfig {
+    fn default() -> Self {
+        CardConfig {
+            width: 360,
+            height: 96,
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use image::{imageops, DynamicImage, RgbaImage};
+use uiua::Primitive;
+
+/// Pictures of the glyphs, the same ones uploaded as the emoji, by primitive name
+const GLYPH_IMAGES: &[(&str, &[u8])] = &[
+    (
+        "absolute value",
+        include_bytes!("../assets/glyphs/absolute value.png"),
+    ),
+    ("add", include_bytes!("../assets/glyphs/add.png")),
+    ("anti", include_bytes!("../assets/glyphs/anti.png")),
+    ("assert", include_bytes!("../assets/glyphs/assert.png")),
+    ("atangent", include_bytes!("../assets/glyphs/atangent.png")),
+    ("below", include_bytes!("../assets/glyphs/below.png")),
+    ("binding", include_bytes!("../assets/glyphs/binding.png")),
+    ("bits", include_bytes!("../assets/glyphs/bits.png")),
+    ("both", include_bytes!("../assets/glyphs/both.png")),
+    ("box", include_bytes!("../assets/glyphs/box.png")),
+    ("bracket", include_bytes!("../assets/glyphs/bracket.png")),
+    ("by", include_bytes!("../assets/glyphs/by.png")),
+    ("ceiling", include_bytes!("../assets/glyphs/ceiling.png")),
+    ("classify", include_bytes!("../assets/glyphs/classify.png")),
+    ("complex", include_bytes!("../assets/glyphs/complex.png")),
+    ("content", include_bytes!("../assets/glyphs/content.png")),
+    ("couple", include_bytes!("../assets/glyphs/couple.png")),
+    (
+        "deduplicate",
+        include_bytes!("../assets/glyphs/deduplicate.png"),
+    ),
+    ("deshape", include_bytes!("../assets/glyphs/deshape.png")),
+    ("dip", include_bytes!("../assets/glyphs/dip.png")),
+    ("divide", include_bytes!("../assets/glyphs/divide.png")),
+    ("do", include_bytes!("../assets/glyphs/do.png")),
+    ("drop", include_bytes!("../assets/glyphs/drop.png")),
+    (
+        "duplicate",
+        include_bytes!("../assets/glyphs/duplicate.png"),
+    ),
+    ("each", include_bytes!("../assets/glyphs/each.png")),
+    ("equals", include_bytes!("../assets/glyphs/equals.png")),
+    ("eta", include_bytes!("../assets/glyphs/eta.png")),
+    ("fall", include_bytes!("../assets/glyphs/fall.png")),
+    ("fill", include_bytes!("../assets/glyphs/fill.png")),
+    ("find", include_bytes!("../assets/glyphs/find.png")),
+    ("first", include_bytes!("../assets/glyphs/first.png")),
+    ("fix", include_bytes!("../assets/glyphs/fix.png")),
+    ("flip", include_bytes!("../assets/glyphs/flip.png")),
+    ("floor", include_bytes!("../assets/glyphs/floor.png")),
+    ("fold", include_bytes!("../assets/glyphs/fold.png")),
+    ("fork", include_bytes!("../assets/glyphs/fork.png")),
+    ("gap", include_bytes!("../assets/glyphs/gap.png")),
+    (
+        "greater or equal",
+        include_bytes!("../assets/glyphs/greater or equal.png"),
+    ),
+    (
+        "greater than",
+        include_bytes!("../assets/glyphs/greater than.png"),
+    ),
+    ("group", include_bytes!("../assets/glyphs/group.png")),
+    ("identity", include_bytes!("../assets/glyphs/identity.png")),
+    ("indexof", include_bytes!("../assets/glyphs/indexof.png")),
+    ("infinity", include_bytes!("../assets/glyphs/infinity.png")),
+    (
+        "inventory",
+        include_bytes!("../assets/glyphs/inventory.png"),
+    ),
+    ("join", include_bytes!("../assets/glyphs/join.png")),
+    ("keep", include_bytes!("../assets/glyphs/keep.png")),
+    ("length", include_bytes!("../assets/glyphs/length.png")),
+    (
+        "less or equal",
+        include_bytes!("../assets/glyphs/less or equal.png"),
+    ),
+    (
+        "less than",
+        include_bytes!("../assets/glyphs/less than.png"),
+    ),
+    (
+        "logarithm",
+        include_bytes!("../assets/glyphs/logarithm.png"),
+    ),
+    ("mask", include_bytes!("../assets/glyphs/mask.png")),
+    ("match", include_bytes!("../assets/glyphs/match.png")),
+    ("maximum", include_bytes!("../assets/glyphs/maximum.png")),
+    ("member", include_bytes!("../assets/glyphs/member.png")),
+    ("minimum", include_bytes!("../assets/glyphs/minimum.png")),
+    ("modulus", include_bytes!("../assets/glyphs/modulus.png")),
+    ("multiply", include_bytes!("../assets/glyphs/multiply.png")),
+    ("negate", include_bytes!("../assets/glyphs/negate.png")),
+    (
+        "not equals",
+        include_bytes!("../assets/glyphs/not equals.png"),
+    ),
+    ("not", include_bytes!("../assets/glyphs/not.png")),
+    ("obverse", include_bytes!("../assets/glyphs/obverse.png")),
+    ("on", include_bytes!("../assets/glyphs/on.png")),
+    ("orient", include_bytes!("../assets/glyphs/orient.png")),
+    ("over", include_bytes!("../assets/glyphs/over.png")),
+    ("parse", include_bytes!("../assets/glyphs/parse.png")),
+    (
+        "partition",
+        include_bytes!("../assets/glyphs/partition.png"),
+    ),
+    ("pi", include_bytes!("../assets/glyphs/pi.png")),
+    ("pick", include_bytes!("../assets/glyphs/pick.png")),
+    ("pop", include_bytes!("../assets/glyphs/pop.png")),
+    ("power", include_bytes!("../assets/glyphs/power.png")),
+    ("random", include_bytes!("../assets/glyphs/random.png")),
+    ("range", include_bytes!("../assets/glyphs/range.png")),
+    ("reduce", include_bytes!("../assets/glyphs/reduce.png")),
+    ("repeat", include_bytes!("../assets/glyphs/repeat.png")),
+    ("rerank", include_bytes!("../assets/glyphs/rerank.png")),
+    ("reshape", include_bytes!("../assets/glyphs/reshape.png")),
+    ("reverse", include_bytes!("../assets/glyphs/reverse.png")),
+    ("rise", include_bytes!("../assets/glyphs/rise.png")),
+    ("rotate", include_bytes!("../assets/glyphs/rotate.png")),
+    ("round", include_bytes!("../assets/glyphs/round.png")),
+    ("rows", include_bytes!("../assets/glyphs/rows.png")),
+    ("scan", include_bytes!("../assets/glyphs/scan.png")),
+    ("select", include_bytes!("../assets/glyphs/select.png")),
+    ("shape", include_bytes!("../assets/glyphs/shape.png")),
+    ("sign", include_bytes!("../assets/glyphs/sign.png")),
+    ("sine", include_bytes!("../assets/glyphs/sine.png")),
+    ("sqrt", include_bytes!("../assets/glyphs/sqrt.png")),
+    ("stack", include_bytes!("../assets/glyphs/stack.png")),
+    ("subtract", include_bytes!("../assets/glyphs/subtract.png")),
+    ("switch", include_bytes!("../assets/glyphs/switch.png")),
+    ("table", include_bytes!("../assets/glyphs/table.png")),
+    ("take", include_bytes!("../assets/glyphs/take.png")),
+    ("tau", include_bytes!("../assets/glyphs/tau.png")),
+    ("trace", include_bytes!("../assets/glyphs/trace.png")),
+    (
+        "transpose",
+        include_bytes!("../assets/glyphs/transpose.png"),
+    ),
+    ("try", include_bytes!("../assets/glyphs/try.png")),
+    ("un", include_bytes!("../assets/glyphs/un.png")),
+    ("under", include_bytes!("../assets/glyphs/under.png")),
+    ("unique", include_bytes!("../assets/glyphs/unique.png")),
+    ("where", include_bytes!("../assets/glyphs/where.png")),
+    ("windows", include_bytes!("../assets/glyphs/windows.png")),
+    ("with", include_bytes!("../assets/glyphs/with.png")),
+];
+
+/// Draws the primitive's glyph as a `size`×`size` PNG with a transparent background, from the
+/// pictures bundled with the emoji. Returns `None` for primitives without a glyph, and for glyphs
+/// newer than the bundled pictures.
+pub fn glyph_image(prim: &Primitive, size: u32) -> Option<Vec<u8>> {
+    let canvas = glyph_picture(prim, size)?;
+    uiua::encode::image_to_bytes(&DynamicImage::from(canvas), image::ImageOutputFormat::Png).ok()
+}
+
+/// Whether [`glyph_image`] has a picture for the primitive, without drawing it
+pub(crate) fn has_glyph_picture(prim: &Primitive) -> bool {
+    prim.glyph().is_some() && GLYPH_IMAGES.iter().any(|(name, _)| *name == prim.name())
+}
+
+/// Same as [`glyph_image`], without encoding the picture
+pub(crate) fn glyph_picture(prim: &Primitive, size: u32) -> Option<RgbaImage> {
+    prim.glyph()?;
+    let (_, bytes) = GLYPH_IMAGES.iter().find(|(name, _)| *name == prim.name())?;
+    let glyph = image::load_from_memory(bytes).ok()?;
+    let glyph = glyph.resize(size, size, imageops::FilterType::Lanczos3);
+
+    let mut canvas = RgbaImage::new(size, size);
+    let left = (size - glyph.width()) / 2;
+    let top = (size - glyph.height()) / 2;
+    imageops::overlay(&mut canvas, &glyph.to_rgba8(), left as i64, top as i64);
+    Some(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs_render_at_the_requested_size() {
+        let png = glyph_image(&Primitive::Add, 48).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (48, 48));
+        assert!(image.to_rgba8().pixels().any(|pixel| pixel.0[3] > 0));
+        assert_eq!(glyph_image(&Primitive::Sys(uiua::SysOp::Show), 48), None);
+    }
+}
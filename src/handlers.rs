@@ -1,9 +1,8 @@
 use std::sync::Arc;
 
 use crate::*;
-use serenity::all::{
-    Builder, CreateAllowedMentions, CreateAttachment, CreateMessage, Embed, Http, Message,
-};
+use serenity::all::{CreateAllowedMentions, CreateAttachment, CreateMessage, Embed, Http, Message};
+use std::mem::take;
 use std::sync::LazyLock;
 use tracing::{debug, error, info, instrument, trace};
 
@@ -38,8 +37,7 @@ Ping <@328851809357791232> for any questions or if you want the version to get b
 
 static MAX_FN_LEN: LazyLock<usize> = LazyLock::new(|| {
     uiua::PrimClass::all()
-        .map(|pc| pc.primitives())
-        .flatten()
+        .flat_map(|pc| pc.primitives())
         .map(|p| {
             p.names()
                 .text
@@ -65,7 +63,7 @@ pub async fn handle_ping(msg: Message, http: Arc<Http>) {
 #[instrument(skip_all)]
 pub async fn handle_version(msg: Message, http: Arc<Http>) {
     trace!("Running version handler");
-    send_message(msg, &http, uiua::VERSION).await
+    send_message(msg, &http, uiua_version()).await
 }
 
 #[instrument(skip_all)]
@@ -77,39 +75,49 @@ pub async fn handle_help(msg: Message, http: Arc<Http>) {
 #[instrument(skip(msg, http))]
 pub async fn handle_fmt(msg: Message, http: Arc<Http>, code: &str) {
     trace!(user = msg.author.name, ?code, "Running fmt handler");
-    send_message(msg, &http, &highlight_code(strip_triple_ticks(code.trim()))).await
+    send_message(msg, &http, &highlight_code(strip_code_fences(code))).await
 }
 
 #[instrument(skip(msg, http))]
 pub async fn handle_pad(msg: Message, http: Arc<Http>, code: &str) {
     trace!(user = msg.author.name, ?code, "Running pad handler");
-    send_message(msg, &http, &format_and_get_pad_link(code.trim())).await;
+    let reply = format_and_get_pad_link(strip_code_fences(code)).unwrap_or_else(|e| e);
+    send_message(msg, &http, &reply).await;
 }
 
 #[instrument(skip(msg, http))]
 pub async fn handle_run(msg: Message, http: Arc<Http>, code: &str) {
     trace!(user = msg.author.name, ?code, "Running run handler");
-    let code = code.trim();
-    let code = strip_triple_ticks(code);
-    // TODO: strip single ticks as well
-    //let code = strip_single_ticks(code);
+    let code = strip_code_fences(code);
 
-    if code.contains("```") {
-        info!(code = %code, "Input contained backticks, disallowing");
+    // A fence left over on either end means the block around the code wasn't closed
+    if code.starts_with("```") || code.ends_with("```") {
+        info!(code = %code, "Input had an unbalanced code fence, disallowing");
         send_message(
             msg,
             &http,
-            "Input contained triple backticks, which I disallow",
+            "Input has a code block that isn't closed, so I can't tell where the code ends",
         )
         .await;
         return;
     }
 
-    let source = highlight_code(code.trim());
-    let result = run_uiua(strip_triple_ticks(code.trim()));
+    let cfg = RunConfig {
+        gif_output: true,
+        deprecation_warnings: true,
+        ..RunConfig::default()
+    };
+    // Backticks in the code, like in a string, would end the block the source is shown in
+    let source = match code.contains("```") {
+        true => "<Source contains triple backticks, so it isn't shown>".to_string(),
+        false => highlight_code(code.trim()),
+    };
+    let result = run_uiua_async(code.to_string(), cfg.clone()).await;
 
     let mut output = String::new();
     let mut attachments = Vec::new();
+    // Position label for the next attachment's file name
+    let mut label = String::new();
     match result {
         Ok(result) => {
             let stack_len = result.len();
@@ -120,7 +128,7 @@ pub async fn handle_run(msg: Message, http: Arc<Http>, code: &str) {
                             .push_str(&format!("<attachment #{}: audio>\n", attachments.len() + 1));
                         attachments.push(CreateAttachment::bytes(
                             bytes,
-                            format!("audio_{}.ogg", attachments.len() + 1),
+                            format!("audio_{}{}.ogg", attachments.len() + 1, take(&mut label)),
                         ));
                     }
                     OutputItem::Image(bytes) => {
@@ -128,27 +136,36 @@ pub async fn handle_run(msg: Message, http: Arc<Http>, code: &str) {
                             .push_str(&format!("<attachment #{}: image>\n", attachments.len() + 1));
                         attachments.push(CreateAttachment::bytes(
                             bytes,
-                            format!("image_{}.png", attachments.len() + 1),
+                            format!("image_{}{}.png", attachments.len() + 1, take(&mut label)),
+                        ));
+                    }
+                    OutputItem::Gif(bytes) => {
+                        output.push_str(&format!("<attachment #{}: gif>\n", attachments.len() + 1));
+                        attachments.push(CreateAttachment::bytes(
+                            bytes,
+                            format!("gif_{}{}.gif", attachments.len() + 1, take(&mut label)),
                         ));
                     }
-                    OutputItem::Misc(val) => {
+                    OutputItem::Misc(_) => {
+                        label.clear();
+                        let text = render_item_text(&item, &cfg).unwrap_or_default();
+                        let text = text.trim_end_matches('\n');
                         if stack_len > 1 {
                             output.push_str(&format!(
                                 "\x1b[{}m{}\x1b[0m",
                                 OUTPUT_COLOR_CYCLE[i % OUTPUT_COLOR_CYCLE.len()],
-                                val.show()
+                                text
                             ))
                         } else {
-                            output.push_str(val.show().as_str())
+                            output.push_str(text)
                         };
                         output.push('\n');
                     }
-                    OutputItem::Continuation(more) => {
-                        output.push_str(&format!(
-                            "<{more} more item{}>\n",
-                            if more == 1 { "" } else { "s" }
-                        ));
+                    OutputItem::Label(ref text) => {
+                        label = format!("_{}", text.trim_matches(['[', ']']));
+                        output.extend(render_item_text(&item, &cfg));
                     }
+                    item => output.extend(render_item_text(&item, &cfg)),
                 }
             }
         }
@@ -158,7 +175,7 @@ pub async fn handle_run(msg: Message, http: Arc<Http>, code: &str) {
     let result = if output.contains("```") {
         info!(?output, "Output contained triple backticks, denying");
         "Output contained triple backticks, which I disallow".to_string()
-    } else if output == "" {
+    } else if output.is_empty() {
         trace!("Resulting stack was empty");
         "<Empty stack>".to_string()
     } else {
@@ -169,6 +186,11 @@ pub async fn handle_run(msg: Message, http: Arc<Http>, code: &str) {
         );
         format!("```ansi\n{output}\n```")
     };
+    let result = match (cfg.spoiler_output, attachments.is_empty()) {
+        (false, _) => result,
+        (true, true) => spoiler_markdown(&result),
+        (true, false) => format!("{}\n(attachments aren't hidden)", spoiler_markdown(&result)),
+    };
 
     let finalized_text = format!("Source:\n{source}\nReturns:\n{result}");
     let shortened_text =
@@ -287,18 +309,3 @@ pub async fn send_message_advanced(msg: Message, http: &Arc<Http>, builder: Crea
         Err(e) => eprintln!("Error sending message: {e}"),
     };
 }
-
-fn strip_triple_ticks(mut s: &str) -> &str {
-    s = s.trim();
-    s = s.strip_prefix("```").unwrap_or(s);
-    s = s.strip_prefix("uiua").unwrap_or(s);
-    s = s.strip_suffix("```").unwrap_or(s);
-    s
-}
-
-fn strip_single_ticks(mut s: &str) -> &str {
-    s = s.trim();
-    s = s.strip_prefix("`").unwrap_or(s);
-    s = s.strip_suffix("`").unwrap_or(s);
-    s
-}
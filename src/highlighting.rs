@@ -1,22 +1,22 @@
 use tracing::trace;
-use uiua::{PrimClass, PrimDocFragment, PrimDocLine, Primitive, Signature, SpanKind, Uiua};
+use uiua::{PrimClass, Primitive, SpanKind};
 
-#[allow(dead_code)]
+/// How a piece of highlighted code is styled, as far as Discord's ANSI support goes
 #[derive(Debug, Clone, Copy, Default)]
-struct AnsiState {
-    color: AnsiColor,
-    bold: bool,
-    italic: bool,
-    dim: bool,
-    underline: bool,
-    blink: bool,
-    reverse: bool,
-    hide: bool,
+pub struct AnsiState {
+    pub color: AnsiColor,
+    pub bold: bool,
+    pub italic: bool,
+    pub dim: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub reverse: bool,
+    pub hide: bool,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, Default)]
-enum AnsiColor {
+pub enum AnsiColor {
     Gray, // Also black
     Red,
     Green,
@@ -31,7 +31,7 @@ enum AnsiColor {
 }
 
 impl AnsiState {
-    fn just_color(col: AnsiColor) -> Self {
+    pub fn just_color(col: AnsiColor) -> Self {
         AnsiState {
             color: col,
             ..Default::default()
@@ -78,12 +78,107 @@ impl AnsiColor {
     }
 }
 
+/// The styles used for each kind of thing in highlighted code
+#[derive(Debug, Clone)]
+pub struct HighlightTheme {
+    pub noadic: AnsiState,
+    pub monadic: AnsiState,
+    pub dyadic: AnsiState,
+    pub monadic_modifier: AnsiState,
+    pub dyadic_modifier: AnsiState,
+    pub constant: AnsiState,
+    pub string: AnsiState,
+    pub number: AnsiState,
+    pub comment: AnsiState,
+    pub output_comment: AnsiState,
+    pub strand: AnsiState,
+    pub label: AnsiState,
+}
+
+impl Default for HighlightTheme {
+    fn default() -> Self {
+        HighlightTheme {
+            noadic: AnsiState::just_color(AnsiColor::Red),
+            monadic: AnsiState::just_color(AnsiColor::Green),
+            dyadic: AnsiState::just_color(AnsiColor::Blue),
+            monadic_modifier: AnsiState::just_color(AnsiColor::Yellow),
+            dyadic_modifier: AnsiState::just_color(AnsiColor::Magenta),
+            constant: AnsiState {
+                color: AnsiColor::Red,
+                bold: true,
+                ..Default::default()
+            },
+            string: AnsiState::just_color(AnsiColor::Cyan),
+            number: AnsiState {
+                color: AnsiColor::Red,
+                bold: true,
+                ..Default::default()
+            },
+            comment: AnsiState {
+                color: AnsiColor::Gray,
+                dim: true,
+                ..Default::default()
+            },
+            output_comment: AnsiState {
+                color: AnsiColor::White,
+                dim: true,
+                ..Default::default()
+            },
+            strand: AnsiState::just_color(AnsiColor::White),
+            label: AnsiState {
+                color: AnsiColor::White,
+                bold: true,
+                italic: true,
+                dim: true,
+                blink: true,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl HighlightTheme {
+    /// Bold everything, drop dimming, and never rely on telling red from green
+    pub fn high_contrast() -> Self {
+        let bold = |color| AnsiState {
+            color,
+            bold: true,
+            ..Default::default()
+        };
+        HighlightTheme {
+            noadic: bold(AnsiColor::Yellow),
+            monadic: bold(AnsiColor::Cyan),
+            dyadic: bold(AnsiColor::Blue),
+            monadic_modifier: bold(AnsiColor::Magenta),
+            dyadic_modifier: AnsiState {
+                underline: true,
+                ..bold(AnsiColor::Magenta)
+            },
+            constant: bold(AnsiColor::Yellow),
+            string: bold(AnsiColor::Cyan),
+            number: bold(AnsiColor::Yellow),
+            comment: AnsiState::just_color(AnsiColor::Gray),
+            output_comment: AnsiState::just_color(AnsiColor::White),
+            strand: bold(AnsiColor::White),
+            label: AnsiState {
+                italic: true,
+                ..bold(AnsiColor::White)
+            },
+        }
+    }
+}
+
 fn with_style(s: &str, ansi: AnsiState) -> String {
     format!("{}{}\x1B[0m", ansi.ansi_str(), s)
 }
 
 /// Returns code surrounded by ANSI backticks to fake highlighting
 pub fn highlight_code(code: &str) -> String {
+    highlight_code_with_theme(code, &HighlightTheme::default())
+}
+
+/// Like [`highlight_code`], but with the colors taken from `theme`
+pub fn highlight_code_with_theme(code: &str, theme: &HighlightTheme) -> String {
     let spans: Vec<_> = uiua::lsp::spans(code).0;
     let mut last_cursor: u32 = 0;
     let mut r: String = spans
@@ -99,45 +194,14 @@ pub fn highlight_code(code: &str) -> String {
             last_cursor = s.span.end.byte_pos;
 
             let fmtd = match s.value {
-                SpanKind::Primitive(p, sig) => print_prim(p, sig),
-                SpanKind::String => with_style(text, AnsiState::just_color(AnsiColor::Cyan)),
-                SpanKind::Number => with_style(
-                    text,
-                    AnsiState {
-                        color: AnsiColor::Red,
-                        bold: true,
-                        ..Default::default()
-                    },
-                ),
-                SpanKind::Comment => with_style(
-                    text,
-                    AnsiState {
-                        color: AnsiColor::Gray,
-                        dim: true,
-                        ..Default::default()
-                    },
-                ),
-                SpanKind::OutputComment => with_style(
-                    text,
-                    AnsiState {
-                        color: AnsiColor::White,
-                        dim: true,
-                        ..Default::default()
-                    },
-                ),
-                SpanKind::Strand => with_style(text, AnsiState::just_color(AnsiColor::White)),
+                SpanKind::Primitive(p, sig) => print_prim(p, sig, theme),
+                SpanKind::String => with_style(text, theme.string),
+                SpanKind::Number => with_style(text, theme.number),
+                SpanKind::Comment => with_style(text, theme.comment),
+                SpanKind::OutputComment => with_style(text, theme.output_comment),
+                SpanKind::Strand => with_style(text, theme.strand),
                 SpanKind::Ident { .. } => with_style(text, AnsiState::default()),
-                SpanKind::Label => with_style(
-                    text,
-                    AnsiState {
-                        color: AnsiColor::White,
-                        bold: true,
-                        italic: true,
-                        dim: true,
-                        blink: true,
-                        ..Default::default()
-                    },
-                ),
+                SpanKind::Label => with_style(text, theme.label),
                 SpanKind::Signature => with_style(text, AnsiState::default()),
                 SpanKind::Whitespace => with_style(text, AnsiState::default()),
                 SpanKind::Placeholder(..) => with_style(text, AnsiState::default()),
@@ -149,24 +213,18 @@ pub fn highlight_code(code: &str) -> String {
                         .map(|c| uiua::SUBSCRIPT_NUMS[(c as u32 as u8 - b'0') as usize])
                         .collect();
                     let style = prim
-                        .map(|p| style_of_prim(p, p.signature().map(|s| s.args)))
+                        .map(|p| style_of_prim(p, p.signature().map(|s| s.args), theme))
                         .unwrap_or_default();
                     with_style(&subs_text, style)
                 }
                 SpanKind::Subscript(_, None) => with_style(text, AnsiState::default()),
                 SpanKind::Obverse(..) => with_style(text, AnsiState::default()),
             };
-            format!(
-                "{}{}",
-                std::iter::repeat('\n')
-                    .take(newlines_skipped)
-                    .collect::<String>(),
-                fmtd
-            )
+            format!("{}{}", "\n".repeat(newlines_skipped), fmtd)
         })
         .collect();
 
-    if r == "" {
+    if r.is_empty() {
         trace!(?code, "Result of highlighting was empty");
         r = "<Empty code>".into();
     } else {
@@ -176,53 +234,55 @@ pub fn highlight_code(code: &str) -> String {
     r
 }
 
-fn style_of_prim(prim: Primitive, sig: Option<usize>) -> AnsiState {
-    let noadic = AnsiState::just_color(AnsiColor::Red);
-    let monadic = AnsiState {
-        color: AnsiColor::Green,
-        ..Default::default()
-    };
-    let monadic_mod = AnsiState {
-        color: AnsiColor::Yellow,
-        ..Default::default()
-    };
-    let dyadic_mod = AnsiState {
-        color: AnsiColor::Magenta,
-        ..Default::default()
-    };
-    let dyadic = AnsiState {
-        color: AnsiColor::Blue,
-        ..Default::default()
-    };
-    let constant = AnsiState {
-        color: AnsiColor::Red,
-        bold: true,
-        ..Default::default()
-    };
-
-    let style = match prim.class() {
+fn style_of_prim(prim: Primitive, sig: Option<usize>, theme: &HighlightTheme) -> AnsiState {
+    match prim.class() {
         PrimClass::Stack | PrimClass::Debug if prim.modifier_args().is_none() => None,
-        PrimClass::Constant => Some(constant),
+        PrimClass::Constant => Some(theme.constant),
         _ => {
             if let Some(margs) = prim.modifier_args() {
-                Some(if margs == 1 { monadic_mod } else { dyadic_mod })
+                Some(if margs == 1 {
+                    theme.monadic_modifier
+                } else {
+                    theme.dyadic_modifier
+                })
             } else {
                 match sig.or(prim.args()) {
-                    Some(0) => Some(noadic),
-                    Some(1) => Some(monadic),
-                    Some(2) => Some(dyadic),
+                    Some(0) => Some(theme.noadic),
+                    Some(1) => Some(theme.monadic),
+                    Some(2) => Some(theme.dyadic),
                     _ => None,
                 }
             }
         }
     }
-    .unwrap_or(AnsiState::default());
-
-    style
+    .unwrap_or_default()
 }
 
-fn print_prim(prim: Primitive, sig: Option<usize>) -> String {
-    let style = style_of_prim(prim, sig);
+fn print_prim(prim: Primitive, sig: Option<usize>, theme: &HighlightTheme) -> String {
+    let style = style_of_prim(prim, sig, theme);
 
     with_style(&prim.to_string(), style)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_theme_matches_plain_highlighting() {
+        assert_eq!(
+            highlight_code_with_theme("⇌⇡5", &HighlightTheme::default()),
+            highlight_code("⇌⇡5")
+        );
+    }
+
+    #[test]
+    fn theme_changes_colors() {
+        let theme = HighlightTheme {
+            monadic: AnsiState::just_color(AnsiColor::Cyan),
+            ..Default::default()
+        };
+        assert!(highlight_code("⇌").contains("\x1B[32m⇌"));
+        assert!(highlight_code_with_theme("⇌", &theme).contains("\x1B[36m⇌"));
+    }
+}
@@ -1,9 +1,31 @@
+#[cfg(feature = "counting-allocator")]
+mod allocation;
+mod backend;
+mod board;
+mod chunking;
+mod config;
+mod glyph_image;
 mod handle_raw_pad_links;
 mod handlers;
 mod highlighting;
+mod preview;
+mod rendering;
+#[cfg(feature = "serde")]
+mod serialization;
 mod uiuaizing;
 
+#[cfg(feature = "counting-allocator")]
+pub use allocation::*;
+pub use backend::*;
+pub use board::*;
+pub use chunking::*;
+pub use config::*;
+pub use glyph_image::*;
 pub use handle_raw_pad_links::*;
 pub use handlers::*;
 pub use highlighting::*;
+pub use preview::*;
+pub use rendering::*;
+#[cfg(feature = "serde")]
+pub use serialization::*;
 pub use uiuaizing::*;
@@ -0,0 +1,135 @@
+use crate::*;
+use uiua::format::{format_str, FormatConfig};
+use uiua::SpanKind;
+
+/// Rough size measurements of a piece of code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeMetrics {
+    pub lines: usize,
+    pub chars: usize,
+    /// How many times any primitive appears
+    pub primitives: usize,
+    pub distinct_primitives: usize,
+}
+
+/// Measures the code without compiling or running it
+pub fn code_metrics(code: &str) -> CodeMetrics {
+    let mut prims: Vec<_> = (uiua::lsp::spans(code).0.into_iter())
+        .filter_map(|span| match span.value {
+            SpanKind::Primitive(prim, _) => Some(prim),
+            _ => None,
+        })
+        .collect();
+    let primitives = prims.len();
+    prims.sort_unstable();
+    prims.dedup();
+    CodeMetrics {
+        lines: code.lines().count(),
+        chars: code.chars().count(),
+        primitives,
+        distinct_primitives: prims.len(),
+    }
+}
+
+/// Everything that can be said about some code without running it
+#[derive(Debug)]
+pub struct PreviewResult {
+    /// The formatted code, or why it couldn't be formatted
+    pub formatted: Result<String, String>,
+    pub pad_link: String,
+    pub metrics: CodeMetrics,
+    /// Parse errors and warnings, each prefixed with where it happened
+    pub diagnostics: Vec<String>,
+}
+
+/// Formats the code, links it and measures it, but never runs it
+pub fn preview(code: &str) -> PreviewResult {
+    let code = strip_code_fences(code);
+    let formatted = format_str(code, &FormatConfig::default())
+        .map(|formatted| formatted.output)
+        .map_err(|e| format!("Error while formatting: {e} "));
+
+    let (_, errors, diags) = uiua::parse(code, (), &mut uiua::Inputs::default());
+    let diagnostics = (errors.iter().map(|e| e.to_string()))
+        .chain(diags.iter().map(|d| format!("{}: {d}", d.span)))
+        .collect();
+
+    PreviewResult {
+        formatted,
+        pad_link: pad_link(code, PadLinkKind::Pad),
+        metrics: code_metrics(code),
+        diagnostics,
+    }
+}
+
+/// Everything a reply to running some code is made of
+#[derive(Debug)]
+pub struct PresentedResult {
+    /// The highlighted code, if [`PresentConfig::echo_source`] is set
+    pub source: Option<String>,
+    /// If [`PresentConfig::include_pad_link`] is set
+    pub pad_link: Option<String>,
+    pub outputs: Result<Vec<OutputItem>, String>,
+}
+
+/// Runs the code and gathers up what a reply shows with its outputs
+pub fn run_and_present(code: &str, cfg: &PresentConfig) -> PresentedResult {
+    let code = strip_code_fences(code);
+    PresentedResult {
+        source: cfg.echo_source.then(|| highlight_code(code.trim())),
+        pad_link: cfg
+            .include_pad_link
+            .then(|| pad_link(code, PadLinkKind::Pad)),
+        outputs: run_uiua_with_config(code, &cfg.run),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn previews_valid_code() {
+        let result = preview("```\nreverse range 5\n+1 1\n```");
+        assert_eq!(result.formatted.unwrap().trim_end(), "⇌ ⇡ 5\n+1 1");
+        assert!(result.pad_link.contains("/pad?src="));
+        assert!(result.diagnostics.is_empty());
+        assert_eq!(
+            result.metrics,
+            CodeMetrics {
+                lines: 2,
+                chars: 20,
+                primitives: 3,
+                distinct_primitives: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn previews_broken_code_without_panicking() {
+        let result = preview("(+1");
+        assert!(result.formatted.is_err());
+        assert!(!result.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn presents_what_is_asked_for() {
+        let code = "```\n+1 2\n```";
+        let result = run_and_present(code, &PresentConfig::default());
+        assert_eq!(result.source, Some(highlight_code("+1 2")));
+        assert_eq!(result.pad_link, None);
+        assert!(matches!(&result.outputs.unwrap()[..], [OutputItem::Misc(v)] if v.show() == "3"));
+
+        let cfg = PresentConfig {
+            echo_source: false,
+            include_pad_link: true,
+            ..PresentConfig::default()
+        };
+        let result = run_and_present(code, &cfg);
+        assert_eq!(result.source, None);
+        assert_eq!(result.pad_link, Some(pad_link("+1 2", PadLinkKind::Pad)));
+
+        let failed = run_and_present("+1", &PresentConfig::default());
+        assert!(failed.source.is_some() && failed.outputs.is_err());
+    }
+}
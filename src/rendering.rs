@@ -0,0 +1,845 @@
+use crate::*;
+use std::collections::HashMap;
+use std::time::Duration;
+use uiua::{Boxed, Value};
+
+/// Marker uiua's grid formatter emits in place of values it refuses to display
+const TRUNCATION_MARKER: &str = "…";
+
+/// Blocks used to draw sparklines, from lowest to highest
+const SPARK_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// How many blocks a sparkline is made of, at most
+const SPARK_WIDTH: usize = 40;
+
+/// Returns the value pretty-printed the way the pad shows it, bounded by the config's limits
+pub fn format_value(value: &Value, cfg: &RunConfig) -> String {
+    let value = match value {
+        Value::Box(arr) if cfg.unwrap_single_box_strings && arr.rank() == 0 => {
+            match arr.elements().next() {
+                Some(Boxed(inner @ Value::Char(text))) if text.rank() <= 1 => inner,
+                _ => value,
+            }
+        }
+        _ => value,
+    };
+    let mut capped = cap_depth(value, cfg.max_depth);
+    if let Some(max_chars) = cfg.max_element_chars {
+        capped = elide_elements(&capped, max_chars);
+    }
+    let mut shown = capped.show();
+    if let (Some(separator), Value::Num(_) | Value::Byte(_)) = (cfg.digit_group_separator, value) {
+        shown = group_digits(&shown, separator);
+    }
+    let too_wide = shown
+        .lines()
+        .any(|line| line.chars().count() > cfg.max_display_columns);
+    if too_wide && cfg.huge_value_mode == HugeValueMode::Summary {
+        if let Some(summary) = value_summary(value) {
+            return summary;
+        }
+    }
+    elide_columns(&shown, cfg.max_display_columns)
+}
+
+/// Groups the digits of every number in the shown numeric array, see
+/// [`RunConfig::digit_group_separator`]. uiua right-aligns each column, so the numbers of a
+/// column all end at the same character, and each column is padded to its widest grouped number.
+fn group_digits(shown: &str, separator: char) -> String {
+    let lines: Vec<(Vec<char>, Vec<ShownNumber>)> = (shown.lines())
+        .map(|line| {
+            let chars: Vec<char> = line.chars().collect();
+            let numbers = find_numbers(&chars, separator);
+            (chars, numbers)
+        })
+        .collect();
+
+    let mut widened: HashMap<usize, usize> = HashMap::new();
+    for (_, numbers) in &lines {
+        for ShownNumber {
+            start,
+            end,
+            grouped,
+        } in numbers
+        {
+            let added = grouped.chars().count() - (end - start);
+            let widest = widened.entry(*end).or_default();
+            *widest = (*widest).max(added);
+        }
+    }
+    let total: usize = widened.values().sum();
+
+    (lines.iter())
+        .map(|(chars, numbers)| {
+            let mut line = String::new();
+            let mut cursor = 0;
+            for ShownNumber {
+                start,
+                end,
+                grouped,
+            } in numbers
+            {
+                line.extend(&chars[cursor..*start]);
+                let added = grouped.chars().count() - (end - start);
+                line.extend(std::iter::repeat_n(' ', widened[end] - added));
+                line.push_str(grouped);
+                cursor = *end;
+            }
+            let rest: String = chars[cursor..].iter().collect();
+            match rest.strip_suffix('╯') {
+                // The closing corner sits at the right edge, which moved by every column's padding
+                Some(before) if numbers.is_empty() => {
+                    line.push_str(before);
+                    line.extend(std::iter::repeat_n(' ', total));
+                    line.push('╯');
+                }
+                _ => line.push_str(&rest),
+            }
+            line
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// A number on a line of a shown array, as where it starts and ends and how it's written once its
+/// digits are grouped
+struct ShownNumber {
+    start: usize,
+    end: usize,
+    grouped: String,
+}
+
+fn find_numbers(chars: &[char], separator: char) -> Vec<ShownNumber> {
+    let is_number = |c: char| c.is_ascii_digit() || c == '.' || c == '¯';
+    let mut numbers = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        if !is_number(chars[start]) {
+            start += 1;
+            continue;
+        }
+        let end = (start..chars.len())
+            .find(|&i| !is_number(chars[i]))
+            .unwrap_or(chars.len());
+        let number: String = chars[start..end].iter().collect();
+        let grouped = group_number(&number, separator);
+        numbers.push(ShownNumber {
+            start,
+            end,
+            grouped,
+        });
+        start = end;
+    }
+    numbers
+}
+
+/// Puts the separator between groups of three digits before the point, if there are more than
+/// four of them
+fn group_number(number: &str, separator: char) -> String {
+    let (sign, unsigned) = match number.strip_prefix('¯') {
+        Some(unsigned) => ("¯", unsigned),
+        None => ("", number),
+    };
+    let (whole, fraction) = unsigned.split_at(unsigned.find('.').unwrap_or(unsigned.len()));
+    if whole.len() <= 4 || !whole.bytes().all(|b| b.is_ascii_digit()) {
+        return number.to_string();
+    }
+    let mut grouped = String::from(sign);
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped.push_str(fraction);
+    grouped
+}
+
+/// Checks that a continuation template only uses the `{n}`, `{s}` and `{end}` placeholders, with
+/// `{n}` appearing at least once
+pub fn check_continuation_template(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    let mut has_count = false;
+    while let Some(start) = rest.find(['{', '}']) {
+        let placeholder = rest[start..]
+            .find('}')
+            .filter(|_| rest[start..].starts_with('{'))
+            .map(|end| &rest[start..=start + end])
+            .ok_or_else(|| format!("Unmatched brace in continuation template {template:?}"))?;
+        match placeholder {
+            "{n}" => has_count = true,
+            "{s}" | "{end}" => {}
+            other => {
+                return Err(format!(
+                    "Unknown placeholder {other} in continuation template"
+                ))
+            }
+        }
+        rest = &rest[start + placeholder.len()..];
+    }
+    if has_count {
+        Ok(())
+    } else {
+        Err("Continuation template needs a {n} for the count".into())
+    }
+}
+
+/// Returns the text shown for `more` stack values hidden from the `end` of the stack, falling back
+/// to the default template if the configured one is invalid
+pub fn render_continuation(more: u32, end: StackEnd, cfg: &RunConfig) -> String {
+    let template = match check_continuation_template(&cfg.continuation_template) {
+        Ok(()) => &cfg.continuation_template,
+        Err(_) => DEFAULT_CONTINUATION_TEMPLATE,
+    };
+    template
+        .replace("{n}", &more.to_string())
+        .replace("{s}", if more == 1 { "" } else { "s" })
+        .replace("{end}", end.name())
+}
+
+/// Returns the text shown for a non-media output item, or `None` for audio, images and GIFs, which
+/// are sent as attachments
+pub fn render_item_text(item: &OutputItem, cfg: &RunConfig) -> Option<String> {
+    match item {
+        OutputItem::Audio(_) | OutputItem::Image(_) | OutputItem::Gif(_) => None,
+        OutputItem::Misc(value) => {
+            let latex = cfg.latex_output.then(|| value_to_latex(value)).flatten();
+            let shown = latex.unwrap_or_else(|| format_value(value, cfg));
+            Some(match cfg.annotate_types {
+                true => format!("({}) {shown}\n", type_annotation(value)),
+                false => format!("{shown}\n"),
+            })
+        }
+        OutputItem::Note(note) => Some(format!("{note}\n")),
+        OutputItem::Label(text) => Some(format!("{text} ")),
+        OutputItem::Continuation(more, end) => {
+            Some(format!("{}\n", render_continuation(*more, *end, cfg)))
+        }
+    }
+}
+
+/// The short name of a value's type. Bytes are only how uiua stores some numbers, so they're
+/// numbers too.
+fn type_annotation(value: &Value) -> &'static str {
+    match value {
+        Value::Num(_) | Value::Byte(_) => "num",
+        Value::Complex(_) => "complex",
+        Value::Char(_) => "char",
+        Value::Box(_) => "box",
+    }
+}
+
+/// How many bytes of text the non-media items take up once rendered, without joining them into
+/// one message or encoding anything. Doesn't count the colors added between multiple values.
+pub fn estimate_rendered_length(items: &[OutputItem]) -> usize {
+    let cfg = RunConfig::default();
+    items
+        .iter()
+        .filter_map(|item| render_item_text(item, &cfg))
+        .map(|text| text.len())
+        .sum()
+}
+
+/// Merges each run of adjacent text items into a single note holding their rendered text, so
+/// they end up in one block. Media stays where it was, and so does a label naming the media after
+/// it.
+pub fn coalesce_text_outputs(items: Vec<OutputItem>) -> Vec<OutputItem> {
+    let cfg = RunConfig::default();
+    let mut coalesced = Vec::new();
+    let mut run: Vec<OutputItem> = Vec::new();
+    let flush = |run: &mut Vec<OutputItem>, coalesced: &mut Vec<OutputItem>| {
+        if run.len() > 1 {
+            let text: String = (run.iter())
+                .filter_map(|item| render_item_text(item, &cfg))
+                .collect();
+            coalesced.push(OutputItem::Note(text.trim_end_matches('\n').to_string()));
+            run.clear();
+        } else {
+            coalesced.append(run);
+        }
+    };
+
+    let mut items = items.into_iter().peekable();
+    while let Some(item) = items.next() {
+        let is_media = |item: Option<&OutputItem>| {
+            matches!(
+                item,
+                Some(OutputItem::Audio(_) | OutputItem::Image(_) | OutputItem::Gif(_))
+            )
+        };
+        let names_media = matches!(item, OutputItem::Label(_)) && is_media(items.peek());
+        if is_media(Some(&item)) || names_media {
+            flush(&mut run, &mut coalesced);
+            coalesced.push(item);
+        } else {
+            run.push(item);
+        }
+    }
+    flush(&mut run, &mut coalesced);
+    coalesced
+}
+
+/// Describes every output as a numbered line of plain text, without emoji or colors, for people
+/// who can't see images or tell colors apart. Labels aren't numbered, as they only say where the
+/// next item came from.
+pub fn render_accessible(items: &[OutputItem]) -> String {
+    let cfg = RunConfig::default();
+    let lines: Vec<String> = (items.iter())
+        .filter_map(|item| match item {
+            OutputItem::Image(bytes) => Some(match image_dimensions(bytes) {
+                Some((width, height)) => format!("image {width}×{height}"),
+                None => "image".to_string(),
+            }),
+            OutputItem::Gif(_) => Some("animation".to_string()),
+            OutputItem::Audio(bytes) => Some(match ogg_duration(bytes) {
+                Some(duration) => format!("audio {:.1}s", duration.as_secs_f64()),
+                None => "audio".to_string(),
+            }),
+            OutputItem::Misc(value) if value.rank() == 0 => {
+                Some(format!("scalar: {}", format_value(value, &cfg)))
+            }
+            OutputItem::Misc(value) => {
+                let shape: Vec<String> =
+                    value.shape().dims().iter().map(usize::to_string).collect();
+                Some(format!(
+                    "array shape [{}]: {}",
+                    shape.join(" "),
+                    format_value(value, &cfg)
+                ))
+            }
+            OutputItem::Note(note) => Some(format!("note: {note}")),
+            OutputItem::Continuation(more, end) => Some(render_continuation(*more, *end, &cfg)),
+            OutputItem::Label(_) => None,
+        })
+        .collect();
+    (lines.iter().enumerate())
+        .map(|(i, line)| format!("{}. {line}", i + 1))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Hides markdown behind Discord spoilers. Spoilers can't reach into or across a code block, so
+/// each code block and each stretch of text between them gets its own, and bars in the text are
+/// escaped so they can't end one early.
+pub fn spoiler_markdown(text: &str) -> String {
+    let mut spoilered = Vec::new();
+    let mut stretch: Vec<String> = Vec::new();
+    let mut block: Option<Vec<&str>> = None;
+    let flush = |stretch: &mut Vec<String>, spoilered: &mut Vec<String>| {
+        if stretch.iter().any(|line| !line.trim().is_empty()) {
+            spoilered.push(format!("||{}||", stretch.join("\n")));
+        } else {
+            spoilered.append(stretch);
+        }
+        stretch.clear();
+    };
+    for line in text.lines() {
+        let fence = line.trim_start().starts_with("```");
+        match (&mut block, fence) {
+            (Some(lines), true) => {
+                lines.push(line);
+                spoilered.push(format!("||{}||", lines.join("\n")));
+                block = None;
+            }
+            (Some(lines), false) => lines.push(line),
+            (None, true) => {
+                flush(&mut stretch, &mut spoilered);
+                block = Some(vec![line]);
+            }
+            (None, false) => stretch.push(line.replace('|', "\\|")),
+        }
+    }
+    flush(&mut stretch, &mut spoilered);
+    // A block that's never closed runs to the end of the message, where a spoiler can't be closed
+    if let Some(lines) = block {
+        spoilered.push(format!("||{}", lines.join("\n")));
+    }
+    spoilered.join("\n")
+}
+
+/// Reads the width and height from an encoded image's header
+fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    image::io::Reader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Works out how long an OGG Vorbis clip plays from its sample rate and the granule position of
+/// its last page, without decoding it
+fn ogg_duration(bytes: &[u8]) -> Option<Duration> {
+    let find = |needle: &[u8]| bytes.windows(needle.len()).position(|w| w == needle);
+    // The identification header is followed by the version, the channel count and the rate
+    let rate_at = find(b"\x01vorbis")? + 7 + 4 + 1;
+    let rate = u32::from_le_bytes(bytes.get(rate_at..rate_at + 4)?.try_into().ok()?);
+    let last_page = bytes.windows(4).rposition(|w| w == b"OggS")?;
+    let granule = u64::from_le_bytes(bytes.get(last_page + 6..last_page + 14)?.try_into().ok()?);
+    (rate > 0).then(|| Duration::from_secs_f64(granule as f64 / rate as f64))
+}
+
+/// Writes a matrix as a LaTeX `bmatrix`, or a list as its elements separated by commas. Returns
+/// `None` for anything that isn't numeric with rank 1 or 2.
+pub fn value_to_latex(value: &Value) -> Option<String> {
+    let data: Vec<f64> = match value {
+        Value::Num(arr) => arr.elements().copied().collect(),
+        Value::Byte(arr) => arr.elements().map(|&b| b as f64).collect(),
+        _ => return None,
+    };
+    let latex_num = |n: &f64| match n {
+        n if n.is_nan() => r"\text{NaN}".to_string(),
+        n if n.is_infinite() => format!("{}\\infty", if *n < 0.0 { "-" } else { "" }),
+        n => short_num(*n),
+    };
+    match *value.shape().dims() {
+        [_] => Some(data.iter().map(latex_num).collect::<Vec<_>>().join(", ")),
+        [_, 0] => Some(r"\begin{bmatrix}\end{bmatrix}".into()),
+        [_, columns] => {
+            let rows: Vec<String> = data
+                .chunks(columns)
+                .map(|row| row.iter().map(latex_num).collect::<Vec<_>>().join(" & "))
+                .collect();
+            Some(format!(
+                "\\begin{{bmatrix}}\n{}\n\\end{{bmatrix}}",
+                rows.join(" \\\\\n")
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Describes a numeric array by its shape, range and mean, followed by a sparkline of its
+/// elements in order. Returns `None` for anything that isn't a non-empty numeric array.
+pub fn value_summary(value: &Value) -> Option<String> {
+    let data: Vec<f64> = match value {
+        Value::Num(arr) => arr.elements().copied().collect(),
+        Value::Byte(arr) => arr.elements().map(|&b| b as f64).collect(),
+        _ => return None,
+    };
+    let finite: Vec<f64> = data.iter().copied().filter(|x| x.is_finite()).collect();
+    if finite.is_empty() {
+        return None;
+    }
+    let min = finite.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = finite.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let mean = finite.iter().sum::<f64>() / finite.len() as f64;
+
+    let per_block = data.len().div_ceil(SPARK_WIDTH);
+    let sparkline: String = data
+        .chunks(per_block)
+        .map(|chunk| {
+            let average = chunk.iter().sum::<f64>() / chunk.len() as f64;
+            let level = if max > min {
+                ((average - min) / (max - min) * (SPARK_BLOCKS.len() - 1) as f64).round()
+            } else {
+                0.0
+            };
+            // NaN or infinite averages end up clamped to one end
+            SPARK_BLOCKS[(level.max(0.0) as usize).min(SPARK_BLOCKS.len() - 1)]
+        })
+        .collect();
+
+    let shape: Vec<String> = value.shape().dims().iter().map(usize::to_string).collect();
+    Some(format!(
+        "shape {}, min {}, max {}, mean {}\n{sparkline}",
+        shape.join("×"),
+        short_num(min),
+        short_num(max),
+        short_num(mean),
+    ))
+}
+
+/// Formats a number to at most 3 decimal places, without trailing zeros
+fn short_num(n: f64) -> String {
+    let s = format!("{n:.3}");
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Cuts the middle out of lines wider than `max_columns`, keeping their start and end.
+/// Every long line is cut at the same columns so grids stay aligned, and the cuts are moved
+/// onto columns that are blank in all of them so numbers aren't split.
+fn elide_columns(shown: &str, max_columns: usize) -> String {
+    let lines: Vec<Vec<char>> = shown.lines().map(|l| l.chars().collect()).collect();
+    let long: Vec<&Vec<char>> = lines.iter().filter(|l| l.len() > max_columns).collect();
+    let Some(width) = long.iter().map(|l| l.len()).min() else {
+        return shown.into();
+    };
+
+    let blank_in_all = |col: usize| long.iter().all(|l| l[col] == ' ');
+    let head = max_columns.saturating_sub(TRUNCATION_MARKER.len()) / 2;
+    let tail_start = width - head;
+    let head = (1..head).rev().find(|&c| blank_in_all(c)).unwrap_or(head);
+    let tail_start = (tail_start..width)
+        .find(|&c| blank_in_all(c))
+        .unwrap_or(tail_start);
+
+    lines
+        .iter()
+        .map(|line| {
+            if line.len() <= max_columns {
+                return line.iter().collect();
+            }
+            let tail_start = tail_start + line.len() - width;
+            let mut elided: String = line[..head].iter().collect();
+            elided.push(' ');
+            elided.push_str(TRUNCATION_MARKER);
+            elided.extend(&line[tail_start..]);
+            elided
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Returns uiua source that evaluates back to the value, unlike [`format_value`] which is only
+/// meant to be looked at
+pub fn value_to_source(value: &Value) -> String {
+    let dims = value.shape().dims();
+    if value.rank() > 1 && dims.contains(&0) {
+        // uiua's repr writes every empty array as `[]`, which loses the shape
+        let shape: Vec<String> = dims.iter().map(usize::to_string).collect();
+        let empty = if let Value::Char(_) = value {
+            "\"\""
+        } else {
+            "[]"
+        };
+        return format!("↯[{}] {empty}", shape.join(" "));
+    }
+    value.representation()
+}
+
+/// Rebuilds the value with every box nested deeper than `depth` replaced by the truncation marker
+fn cap_depth(value: &Value, depth: usize) -> Value {
+    match value {
+        Value::Box(arr) if depth == 0 => {
+            Value::Box(arr.convert_ref_with(|_| Boxed(TRUNCATION_MARKER.into())))
+        }
+        Value::Box(arr) => {
+            Value::Box(arr.convert_ref_with(|Boxed(inner)| Boxed(cap_depth(&inner, depth - 1))))
+        }
+        _ => value.clone(),
+    }
+}
+
+/// Cuts boxed strings longer than `max_chars` short, at any depth, leaving the rest of the array
+fn elide_elements(value: &Value, max_chars: usize) -> Value {
+    match value {
+        Value::Box(arr) => Value::Box(arr.convert_ref_with(|Boxed(inner)| {
+            Boxed(match &inner {
+                Value::Char(text) if text.rank() <= 1 && text.row_count() > max_chars => {
+                    let mut elided: String = text.elements().take(max_chars).collect();
+                    elided.push_str(TRUNCATION_MARKER);
+                    Value::from(elided)
+                }
+                _ => elide_elements(&inner, max_chars),
+            })
+        })),
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uiua::Array;
+
+    fn eval(code: &str) -> Value {
+        let mut rt = uiua::Uiua::with_safe_sys();
+        rt.run_str(code).unwrap();
+        rt.pop("value").unwrap()
+    }
+
+    fn nested_boxes(depth: usize) -> Value {
+        (0..depth).fold(Value::from(1.0), |v, _| Value::Box(Array::scalar(Boxed(v))))
+    }
+
+    #[test]
+    fn shallow_boxes_untouched() {
+        let value = nested_boxes(3);
+        assert_eq!(format_value(&value, &RunConfig::default()), value.show());
+    }
+
+    #[test]
+    fn deep_boxes_truncated() {
+        let cfg = RunConfig {
+            max_depth: 5,
+            ..Default::default()
+        };
+        let shown = format_value(&nested_boxes(1000), &cfg);
+        assert!(shown.contains(TRUNCATION_MARKER));
+        assert!(shown.chars().count() < 100);
+    }
+
+    #[test]
+    fn wide_arrays_elided() {
+        let row = eval("[⇡200]");
+        let cfg = RunConfig {
+            max_display_columns: 60,
+            ..Default::default()
+        };
+        let shown = format_value(&row, &cfg);
+        assert!(shown.lines().all(|l| l.chars().count() <= 60), "{shown}");
+        assert!(shown.contains(" 1 2 3 "));
+        assert!(shown.contains(" 198 199"));
+        assert!(shown.contains(TRUNCATION_MARKER));
+    }
+
+    #[test]
+    fn narrow_arrays_untouched() {
+        let value = eval("⊞+.⇡3");
+        assert_eq!(format_value(&value, &RunConfig::default()), value.show());
+    }
+
+    #[test]
+    fn source_round_trips() {
+        for code in [
+            "⇡5",
+            "↯2_3÷2⇡6",
+            r#""hello world""#,
+            r#"{1 "ab" [2 3]}"#,
+            "ℂ1 2",
+            "↯0_3 []",
+            "¯∞",
+        ] {
+            let value = eval(code);
+            let source = value_to_source(&value);
+            let mut stack = eval_uiua(&source, &RunConfig::default()).unwrap();
+            assert_eq!(stack.pop(), Some(value), "{code} became {source}");
+        }
+    }
+
+    #[test]
+    fn summarizes_numeric_arrays() {
+        let summary = value_summary(&eval("↯3_200⇡600")).unwrap();
+        let (stats, sparkline) = summary.split_once('\n').unwrap();
+        assert_eq!(stats, "shape 3×200, min 0, max 599, mean 299.5");
+        assert_eq!(sparkline.chars().count(), SPARK_WIDTH);
+        assert!(sparkline.starts_with('▁') && sparkline.ends_with('█'));
+        assert_eq!(value_summary(&eval(r#""text""#)), None);
+    }
+
+    #[test]
+    fn summary_mode_replaces_wide_values() {
+        let cfg = RunConfig {
+            huge_value_mode: HugeValueMode::Summary,
+            ..Default::default()
+        };
+        assert!(format_value(&eval("⇡200"), &cfg).starts_with("shape 200,"));
+        assert_eq!(format_value(&eval("⇡5"), &cfg), eval("⇡5").show());
+    }
+
+    #[test]
+    fn continuation_template_is_filled_in() {
+        let mut cfg = RunConfig::default();
+        assert_eq!(
+            render_continuation(1, StackEnd::Top, &cfg),
+            "<1 more item at the top of the stack>"
+        );
+        assert_eq!(
+            render_continuation(3, StackEnd::Bottom, &cfg),
+            "<3 more items at the bottom of the stack>"
+        );
+        cfg.continuation_template = "Hey, there's {n} more value{s}!".into();
+        assert_eq!(
+            render_continuation(2, StackEnd::Top, &cfg),
+            "Hey, there's 2 more values!"
+        );
+        cfg.continuation_template = "{count} hidden".into();
+        assert_eq!(
+            render_continuation(2, StackEnd::Top, &cfg),
+            "<2 more items at the top of the stack>"
+        );
+    }
+
+    #[test]
+    fn bad_continuation_templates_are_rejected() {
+        assert!(check_continuation_template("+{n}").is_ok());
+        assert!(check_continuation_template("+{n} from the {end}").is_ok());
+        assert!(check_continuation_template("more").is_err());
+        assert!(check_continuation_template("{n} {x}").is_err());
+        assert!(check_continuation_template("{n").is_err());
+        assert!(check_continuation_template("n} {n}").is_err());
+    }
+
+    #[test]
+    fn rendered_length_matches_rendering() {
+        let cfg = RunConfig {
+            label_stack_positions: true,
+            ..RunConfig::default()
+        };
+        let items =
+            run_uiua_with_config(&format!("{}\"hi\" ÷99⊞+.⇡50", "5 ".repeat(12)), &cfg).unwrap();
+        assert!(items
+            .iter()
+            .any(|item| matches!(item, OutputItem::Image(_))));
+        assert!(items
+            .iter()
+            .any(|item| matches!(item, OutputItem::Continuation(..))));
+        let rendered: String = items
+            .iter()
+            .filter_map(|item| render_item_text(item, &cfg))
+            .collect();
+        assert_eq!(estimate_rendered_length(&items), rendered.len());
+        assert_eq!(rendered.matches("5\n").count(), 8);
+    }
+
+    #[test]
+    fn long_boxed_strings_are_elided() {
+        let value = eval(&format!("{{\"short\" \"{}\" 5}}", "a".repeat(50)));
+        let cfg = RunConfig {
+            max_element_chars: Some(10),
+            ..RunConfig::default()
+        };
+        let shown = format_value(&value, &cfg);
+        assert!(shown.contains(&format!("\"{}…\"", "a".repeat(10))));
+        assert!(shown.contains("\"short\""));
+        assert!(!shown.contains(&"a".repeat(11)));
+        assert!(format_value(&value, &RunConfig::default()).contains(&"a".repeat(50)));
+    }
+
+    #[test]
+    fn single_boxed_strings_unwrapped() {
+        let boxed = eval("□\"hi\"");
+        let cfg = RunConfig {
+            unwrap_single_box_strings: true,
+            ..RunConfig::default()
+        };
+        assert_eq!(format_value(&boxed, &RunConfig::default()), boxed.show());
+        assert_ne!(boxed.show(), "\"hi\"");
+        assert_eq!(format_value(&boxed, &cfg), "\"hi\"");
+
+        let list = eval("{\"a\" \"b\"}");
+        assert_eq!(format_value(&list, &cfg), list.show());
+    }
+
+    #[test]
+    fn numeric_columns_stay_aligned() {
+        let value = eval("[1_22_333 4444_5_66 7_888_9]");
+        let expected = [
+            "╭─              ",
+            "╷    1  22 333  ",
+            "  4444   5  66  ",
+            "     7 888   9  ",
+            "               ╯",
+        ];
+        assert_eq!(
+            format_value(&value, &RunConfig::default()),
+            expected.join("\n")
+        );
+
+        let cfg = RunConfig {
+            digit_group_separator: Some(','),
+            ..RunConfig::default()
+        };
+        let value = eval("[123456_1_22 7_¯1234567.5_3 8_9_1234]");
+        let expected = [
+            "╭─                        ",
+            "╷ 123,456            1   22  ",
+            "        7 ¯1,234,567.5    3  ",
+            "        8            9 1234  ",
+            "                            ╯",
+        ];
+        assert_eq!(format_value(&value, &cfg), expected.join("\n"));
+    }
+
+    #[test]
+    fn matches_pad_display() {
+        let cfg = RunConfig::default();
+        for code in [
+            "5",
+            "¯1.5 π",
+            "\"hello\" @w",
+            "↯2_3_4⇡24",
+            "{1 \"two\" [3 4]}",
+            "ℂ2 1",
+            "\"\" 0",
+            "↯3_3 \"abcdefghi\"",
+        ] {
+            let rendered: String = (run_uiua(code).unwrap().iter())
+                .filter_map(|item| render_item_text(item, &cfg))
+                .collect();
+            assert_eq!(
+                rendered.trim_end(),
+                pad_equivalent_text(code).unwrap(),
+                "{code}"
+            );
+        }
+    }
+
+    #[test]
+    fn types_annotated() {
+        let cfg = RunConfig {
+            annotate_types: true,
+            ..RunConfig::default()
+        };
+        let annotated = |code| render_item_text(&OutputItem::Misc(eval(code)), &cfg).unwrap();
+        assert_eq!(annotated("5"), "(num) 5\n");
+        assert_eq!(annotated("=1 1"), "(num) 1\n");
+        assert_eq!(annotated("@a"), "(char) @a\n");
+        assert!(annotated("{1 \"a\"}").starts_with("(box) {"));
+        assert_eq!(
+            render_item_text(&OutputItem::Misc(eval("5")), &RunConfig::default()).unwrap(),
+            "5\n"
+        );
+    }
+
+    #[test]
+    fn adjacent_text_is_coalesced() {
+        let cfg = RunConfig {
+            label_stack_positions: true,
+            ..RunConfig::default()
+        };
+        let items = run_uiua_with_config("1 2 ÷99⊞+.⇡50 \"a\" \"b\"", &cfg).unwrap();
+        let coalesced = coalesce_text_outputs(items);
+        let [OutputItem::Note(before), OutputItem::Label(label), OutputItem::Image(_), OutputItem::Note(after)] =
+            coalesced.as_slice()
+        else {
+            panic!("unexpected outputs: {coalesced:?}");
+        };
+        assert_eq!(before, "[4] \"b\"\n[3] \"a\"");
+        assert_eq!(label, "[2]");
+        assert_eq!(after, "[1] 2\n[top] 1");
+
+        let single = coalesce_text_outputs(run_uiua("5").unwrap());
+        assert!(matches!(single.as_slice(), [OutputItem::Misc(_)]));
+    }
+
+    #[test]
+    fn accessible_rendering_describes_each_output() {
+        let items = run_uiua("5 ↯3_4@a ∿×τ×220÷44100⇡92610 ÷64⊞+.⇡64").unwrap();
+        let rendered = render_accessible(&items);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], "1. image 64×64");
+        assert_eq!(lines[1], "2. audio 2.1s");
+        assert!(lines[2].starts_with("3. array shape [3 4]: "));
+        assert_eq!(lines.last(), Some(&"4. scalar: 5"));
+        assert!(!rendered.contains('\x1b') && !rendered.contains('🔊'));
+    }
+
+    #[test]
+    fn spoilers_wrap_around_code_blocks() {
+        let text = "Returns:\n```ansi\n1 2 3\n```\na || b";
+        let spoilered = spoiler_markdown(text);
+        assert_eq!(
+            spoilered,
+            "||Returns:||\n||```ansi\n1 2 3\n```||\n||a \\|\\| b||"
+        );
+        // Both fences are still there, right inside the bars
+        assert_eq!(
+            (spoilered.lines())
+                .filter(|line| line.trim_start_matches('|').starts_with("```"))
+                .count(),
+            2
+        );
+        assert_eq!(spoiler_markdown("```ansi\n||\n```"), "||```ansi\n||\n```||");
+    }
+
+    #[test]
+    fn matrices_become_latex() {
+        assert_eq!(
+            value_to_latex(&eval("[[1 2] [3 ¯4.5]]")).unwrap(),
+            "\\begin{bmatrix}\n1 & 2 \\\\\n3 & -4.5\n\\end{bmatrix}"
+        );
+        assert_eq!(value_to_latex(&eval("[1 ∞ 3]")).unwrap(), "1, \\infty, 3");
+        assert_eq!(value_to_latex(&eval("\"hi\"")), None);
+        assert_eq!(value_to_latex(&eval("↯2_2_2 0")), None);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::*;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Serialize;
+
+/// An [`OutputItem`] in a form that can be handed to things other than Discord
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SerializedOutput {
+    /// Encoded media, with `data` in base64
+    Audio {
+        mime: &'static str,
+        data: String,
+    },
+    Image {
+        mime: &'static str,
+        data: String,
+    },
+    /// A value shown as text, like the pad would
+    Misc {
+        text: String,
+        shape: Vec<usize>,
+        type_name: String,
+    },
+    Continuation {
+        count: u32,
+        /// `top` or `bottom`, whichever end of the stack they were cut from
+        end: &'static str,
+    },
+    Note {
+        text: String,
+    },
+    Label {
+        text: String,
+    },
+}
+
+impl SerializedOutput {
+    pub fn new(item: &OutputItem, cfg: &RunConfig) -> Self {
+        match item {
+            OutputItem::Audio(bytes) => SerializedOutput::Audio {
+                mime: "audio/ogg",
+                data: STANDARD.encode(bytes),
+            },
+            OutputItem::Image(bytes) => SerializedOutput::Image {
+                mime: "image/png",
+                data: STANDARD.encode(bytes),
+            },
+            OutputItem::Gif(bytes) => SerializedOutput::Image {
+                mime: "image/gif",
+                data: STANDARD.encode(bytes),
+            },
+            OutputItem::Misc(value) => SerializedOutput::Misc {
+                text: format_value(value, cfg),
+                shape: value.shape().dims().to_vec(),
+                type_name: value.type_name().into(),
+            },
+            OutputItem::Continuation(count, end) => SerializedOutput::Continuation {
+                count: *count,
+                end: end.name(),
+            },
+            OutputItem::Note(text) => SerializedOutput::Note { text: text.clone() },
+            OutputItem::Label(text) => SerializedOutput::Label { text: text.clone() },
+        }
+    }
+}
+
+/// The media as a `data:` URI, for embedding it without a file. `None` for items that aren't media
+pub fn output_to_data_uri(item: &OutputItem) -> Option<String> {
+    let (mime, bytes) = match item {
+        OutputItem::Audio(bytes) => ("audio/ogg", bytes),
+        OutputItem::Image(bytes) => ("image/png", bytes),
+        OutputItem::Gif(bytes) => ("image/gif", bytes),
+        _ => return None,
+    };
+    Some(format!("data:{mime};base64,{}", STANDARD.encode(bytes)))
+}
+
+/// Runs the code and returns its outputs as a JSON array
+pub fn run_uiua_json(code: &str) -> Result<String, String> {
+    let cfg = RunConfig::default();
+    let outputs: Vec<SerializedOutput> = run_uiua_with_config(code, &cfg)?
+        .iter()
+        .map(|item| SerializedOutput::new(item, &cfg))
+        .collect();
+    serde_json::to_string(&outputs).map_err(|e| format!("Error while serializing: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn misc_has_text_and_metadata() {
+        let json: Value =
+            serde_json::from_str(&run_uiua_json(r#"↯3_2 "abcdef""#).unwrap()).unwrap();
+        assert_eq!(json[0]["kind"], "misc");
+        assert_eq!(json[0]["shape"], json!([3, 2]));
+        assert_eq!(json[0]["type_name"], "character");
+        assert!(json[0]["text"].as_str().unwrap().contains('╭'));
+    }
+
+    #[test]
+    fn media_is_base64_with_mime() {
+        let json: Value = serde_json::from_str(&run_uiua_json("÷99⊞+.⇡50").unwrap()).unwrap();
+        assert_eq!(json[0]["kind"], "image");
+        assert_eq!(json[0]["mime"], "image/png");
+        let png = STANDARD.decode(json[0]["data"].as_str().unwrap()).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn media_becomes_data_uri() {
+        let outputs = run_uiua("÷99⊞+.⇡50").unwrap();
+        let uri = output_to_data_uri(&outputs[0]).unwrap();
+        let data = uri.strip_prefix("data:image/png;base64,").unwrap();
+        let png = STANDARD.decode(data).unwrap();
+        assert!(image::load_from_memory(&png).is_ok());
+
+        assert_eq!(output_to_data_uri(&OutputItem::Note("hi".into())), None);
+    }
+
+    #[test]
+    fn continuation_is_a_count() {
+        let json: Value = serde_json::from_str(&run_uiua_json("⍥(1)12").unwrap()).unwrap();
+        let last = json.as_array().unwrap().last().unwrap();
+        assert_eq!(last["kind"], "continuation");
+        assert!(last["count"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn errors_stay_errors() {
+        assert!(run_uiua_json("+").is_err());
+    }
+}
@@ -1,19 +1,52 @@
-use std::time::Duration;
-
 use crate::*;
 use base64::Engine;
-use tracing::trace;
+use tracing::{trace, warn};
 use uiua::format::*;
-use uiua::{PrimDocFragment, PrimDocLine, Primitive, Uiua};
+use uiua::{
+    Compiler, LexError, ParseError, PreEvalMode, PrimDoc, PrimDocFragment, PrimDocLine, Primitive,
+    SafeSys, Sp, SpanKind, SysBackend, Token, Uiua, UiuaError, UiuaErrorKind,
+};
 
 use base64::engine::general_purpose::URL_SAFE;
-use std::collections::HashMap;
-use std::sync::LazyLock;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, LazyLock, Mutex, Once, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The version of uiua code is run, formatted, and linked with
+pub const UIUA_VERSION: &str = uiua::VERSION;
 
 const MIN_AUTO_IMAGE_DIM: usize = 30;
+/// Past this many rows or columns a matrix is more likely data than something to look at
+const MAX_HEATMAP_DIM: usize = 512;
+/// A tenth of a second at uiua's sample rate. Matrices with more than 5 rows are only taken as
+/// audio when they're at least this long.
+const MIN_MULTICHANNEL_SAMPLES: usize = 4410;
+/// Evenly spaced samples of matplotlib's viridis colormap, from low to high
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 145, 140],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
 const MAX_STACK_VALS_DISPLAYED: usize = 10;
-const DEFAULT_EXECUTION_LIMIT: Duration = Duration::from_secs(2);
-const EMOJI_IDS: &'static str = include_str!("../assets/glyphlist.txt");
+/// Discord won't show more autocomplete choices than this
+const MAX_COMPLETIONS: usize = 25;
+/// Enough examples to learn from without listing half the docs
+const MAX_EXAMPLES: usize = 50;
+/// Each one is a whole run, so only the first few of a primitive's examples are run
+const MAX_LIVE_EXAMPLES: usize = 5;
+/// Every snapshot is a run of its own, so loops can't make this many more than it already is
+const MAX_TRACE_SNAPSHOTS: usize = 100;
+/// Frames per second animations on the stack are played at
+pub const GIF_FRAME_RATE: f64 = 20.0;
+const EXPERIMENTAL_PRAGMA: &str = "# Experimental!\n";
+const EMOJI_IDS: &str = include_str!("../assets/glyphlist.txt");
 static EMOJI_MAP: LazyLock<HashMap<&str, &str>> = LazyLock::new(|| {
     EMOJI_IDS
         .lines()
@@ -28,182 +61,4048 @@ static EMOJI_MAP: LazyLock<HashMap<&str, &str>> = LazyLock::new(|| {
         .collect::<HashMap<&str, &str>>()
 });
 
+#[derive(Debug)]
 pub enum OutputItem {
     /// Audio, containing encoded OGG Vorbis bytes.
     Audio(Box<[u8]>),
     /// Static image data, containing encoded PNG bytes.
     Image(Box<[u8]>),
+    /// Animation, containing encoded GIF bytes.
+    Gif(Box<[u8]>),
     /// Miscellaneous value.
     Misc(uiua::Value),
-    /// "Hey, there's {n} more values!" indicator
-    Continuation(u32),
+    /// "Hey, there's {n} more values!" indicator, with the end of the stack they were cut from
+    Continuation(u32, StackEnd),
+    /// Remark about how the preceding item was displayed
+    Note(String),
+    /// Where on the stack the items after it came from, like `[top]` or `[2]`
+    Label(String),
     // TODO: images, gifs, you know the drill
 }
 
-impl From<uiua::Value> for OutputItem {
-    fn from(value: uiua::Value) -> Self {
-        use uiua::encode::*;
-        use uiua::Value;
-
-        fn try_from_ogg(value: &Value) -> Result<OutputItem, Box<dyn std::error::Error>> {
-            let channels: Vec<Vec<f32>> = value_to_audio_channels(&value)?
-                .into_iter()
-                .map(|v| v.into_iter().map(|x| x as f32).collect())
-                .collect();
-            let mut sink = Vec::new();
-            let mut encoder = vorbis_rs::VorbisEncoderBuilder::new(
-                std::num::NonZeroU32::new(44100).ok_or("unreachable")?,
-                std::num::NonZeroU8::new(channels.len() as u8).ok_or("unreachable")?,
-                &mut sink,
-            )?
-            .build()?;
-            encoder.encode_audio_block(channels)?;
-            encoder.finish()?;
-            Ok(OutputItem::Audio(sink.into_boxed_slice()))
-        }
-
-        if let Ok(this) = try_from_ogg(&value) {
-            return this;
-        }
-        if let Ok(image) = value_to_image(&value) {
-            if image.width() >= MIN_AUTO_IMAGE_DIM as u32
-                && image.height() >= MIN_AUTO_IMAGE_DIM as u32
-            {
-                if let Ok(bytes) = image_to_bytes(&image, image::ImageOutputFormat::Png) {
-                    return OutputItem::Image(bytes.into());
+/// What an [`OutputItem`] is and how big its encoded data is, for logging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputDescriptor {
+    pub kind: &'static str,
+    /// Size of the encoded media, 0 for items that aren't media
+    pub bytes: usize,
+}
+
+/// One end of the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackEnd {
+    Top,
+    Bottom,
+}
+
+impl StackEnd {
+    pub fn name(self) -> &'static str {
+        match self {
+            StackEnd::Top => "top",
+            StackEnd::Bottom => "bottom",
+        }
+    }
+}
+
+impl OutputItem {
+    pub fn descriptor(&self) -> OutputDescriptor {
+        let (kind, bytes) = match self {
+            OutputItem::Audio(bytes) => ("audio", bytes.len()),
+            OutputItem::Image(bytes) => ("image", bytes.len()),
+            OutputItem::Gif(bytes) => ("gif", bytes.len()),
+            OutputItem::Misc(_) => ("misc", 0),
+            OutputItem::Continuation(..) => ("continuation", 0),
+            OutputItem::Note(_) => ("note", 0),
+            OutputItem::Label(_) => ("label", 0),
+        };
+        OutputDescriptor { kind, bytes }
+    }
+}
+
+/// Summarizes the outputs by kind, like "2 images (12KB, 8KB), 1 misc"
+pub fn summarize_outputs(items: &[OutputItem]) -> String {
+    let mut kinds: Vec<(&str, Vec<usize>)> = Vec::new();
+    for desc in items.iter().map(OutputItem::descriptor) {
+        match kinds.iter_mut().find(|(kind, _)| *kind == desc.kind) {
+            Some((_, sizes)) => sizes.push(desc.bytes),
+            None => kinds.push((desc.kind, vec![desc.bytes])),
+        }
+    }
+    kinds
+        .iter()
+        .map(|(kind, sizes)| {
+            let plural = if sizes.len() > 1 && !matches!(*kind, "audio" | "misc") {
+                "s"
+            } else {
+                ""
+            };
+            let mut summary = format!("{} {kind}{plural}", sizes.len());
+            if sizes.iter().any(|&bytes| bytes > 0) {
+                let sizes: Vec<String> = (sizes.iter())
+                    .map(|bytes| format!("{}KB", bytes.div_ceil(1024)))
+                    .collect();
+                summary.push_str(&format!(" ({})", sizes.join(", ")));
+            }
+            summary
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// What a stack value will be shown as, without doing the encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    Audio,
+    Image,
+    Misc,
+}
+
+/// The kind a whole result is mainly made of, for picking how to send it. Audio wins over images,
+/// and images win over text, since a single clip or picture is usually the point of the code.
+/// Notes, labels and the like count as text.
+pub fn dominant_output_kind(items: &[OutputItem]) -> OutputKind {
+    let has = |kind: fn(&OutputItem) -> bool| items.iter().any(kind);
+    if has(|item| matches!(item, OutputItem::Audio(_))) {
+        OutputKind::Audio
+    } else if has(|item| matches!(item, OutputItem::Image(_) | OutputItem::Gif(_))) {
+        OutputKind::Image
+    } else {
+        OutputKind::Misc
+    }
+}
+
+/// Decides how a value would be displayed by looking only at its type and shape
+pub fn classify_value(value: &uiua::Value, cfg: &RunConfig) -> OutputKind {
+    use uiua::Value;
+
+    if !matches!(value, Value::Num(_) | Value::Byte(_)) {
+        return OutputKind::Misc;
+    }
+    let image_sized = |height, width| height >= MIN_AUTO_IMAGE_DIM && width >= MIN_AUTO_IMAGE_DIM;
+    match *value.shape().dims() {
+        // Written out as LaTeX instead
+        [_] if cfg.latex_output => OutputKind::Misc,
+        [height, width] if cfg.latex_output && !image_sized(height, width) => OutputKind::Misc,
+        [1..=MAX_HEATMAP_DIM, 1..=MAX_HEATMAP_DIM] if cfg.heatmap => OutputKind::Image,
+        [_] if cfg.audio_output => OutputKind::Audio,
+        [channels, _] if cfg.audio_output && channels <= 5 => OutputKind::Audio,
+        [height, width] | [height, width, 1..=4] if image_sized(height, width) => OutputKind::Image,
+        [_, height, width] | [_, height, width, 1..=4]
+            if cfg.gif_output && image_sized(height, width) =>
+        {
+            OutputKind::Image
+        }
+        // Too many rows to be short audio, but too long to be anything else
+        [channels, samples]
+            if cfg.audio_output
+                && channels <= u8::MAX as usize
+                && samples >= MIN_MULTICHANNEL_SAMPLES =>
+        {
+            OutputKind::Audio
+        }
+        [_, _] | [_, _, 1..=4] if cfg.small_image_mode != SmallImageMode::Misc => OutputKind::Image,
+        _ => OutputKind::Misc,
+    }
+}
+
+/// Whether an image sized value is an animation, a rank 3 or 4 array of frames, rather than a
+/// still image with color channels
+fn is_animation(value: &uiua::Value) -> bool {
+    match value.shape().dims() {
+        [_, _, _, _] => true,
+        // A still image has at most 4 channels, and a frame is wider than that
+        &[_, _, last] => last > 4,
+        _ => false,
+    }
+}
+
+/// Drops the frames of an animation, a rank 3 or 4 array of frames, past the first `max_frames`,
+/// returning what's left and a note saying it was cut short
+pub fn cap_animation_frames(
+    value: uiua::Value,
+    max_frames: usize,
+) -> (uiua::Value, Option<String>) {
+    if !(3..=4).contains(&value.rank()) || value.row_count() <= max_frames {
+        return (value, None);
+    }
+    let frames: Vec<uiua::Value> = value.rows().take(max_frames).collect();
+    (
+        uiua::Value::from_row_values_infallible(frames),
+        Some(format!("(truncated to {max_frames} frames)")),
+    )
+}
+
+/// Encodes an animation as a GIF after capping its frames at [`RunConfig::max_gif_frames`], or
+/// `None` if it can't be encoded or comes out bigger than [`RunConfig::max_image_bytes`]
+fn gif_outputs(value: &uiua::Value, cfg: &RunConfig) -> Option<Vec<OutputItem>> {
+    let (value, note) = cap_animation_frames(value.clone(), cfg.max_gif_frames.max(1));
+    let bytes = uiua::encode::value_to_gif_bytes(&value, GIF_FRAME_RATE).ok()?;
+    if bytes.len() > cfg.max_image_bytes {
+        trace!(bytes = bytes.len(), "GIF too big to send");
+        return None;
+    }
+    Some(
+        ([OutputItem::Gif(bytes.into())].into_iter())
+            .chain(note.map(OutputItem::Note))
+            .collect(),
+    )
+}
+
+/// Converts a stack value into the items used to display it, picking a media encoding if one fits.
+/// `index` is how far from the top of the stack the value was, with 0 being the top.
+pub fn value_to_outputs(value: uiua::Value, index: usize, cfg: &RunConfig) -> Vec<OutputItem> {
+    let mut items = Vec::new();
+    if cfg.label_stack_positions {
+        items.push(OutputItem::Label(match index {
+            0 => "[top]".into(),
+            n => format!("[{n}]"),
+        }));
+    }
+    if index == 0 && cfg.force_render_top_media && cfg.small_image_mode == SmallImageMode::Misc {
+        let top_cfg = RunConfig {
+            small_image_mode: SmallImageMode::Upscale,
+            ..cfg.clone()
+        };
+        items.extend(encode_value(value, &top_cfg));
+    } else {
+        items.extend(encode_value(value, cfg));
+    }
+    items
+}
+
+fn encode_value(value: uiua::Value, cfg: &RunConfig) -> Vec<OutputItem> {
+    use uiua::encode::value_to_image;
+
+    match classify_value(&value, cfg) {
+        OutputKind::Audio => {
+            if let Ok(mut samples) = audio_channels(&value) {
+                let max_channels = cfg.max_audio_channels.max(1);
+                if samples.len() > max_channels {
+                    match cfg.channel_overflow {
+                        ChannelOverflow::Downmix => samples = downmix(&samples, max_channels),
+                        ChannelOverflow::Decline => {
+                            let note = format!(
+                                "(not played, {} channels is more than the {max_channels} allowed)",
+                                samples.len()
+                            );
+                            return vec![OutputItem::Misc(value), OutputItem::Note(note)];
+                        }
+                    }
+                }
+                if let Ok(bytes) = encode_ogg(&samples, cfg.sample_rate) {
+                    let mut items = vec![OutputItem::Audio(bytes)];
+                    let plots = [
+                        cfg.render_waveform.then(|| render_waveform(&samples)),
+                        cfg.audio_thumbnail
+                            .then(|| render_audio_thumbnail(&samples)),
+                    ];
+                    for plot in plots.into_iter().flatten() {
+                        if let Ok((bytes, _)) =
+                            encode_png_within(plot, cfg.max_image_bytes, cfg.png_compression)
+                        {
+                            items.push(OutputItem::Image(bytes.into()));
+                        }
+                    }
+                    return items;
+                }
+            }
+        }
+        OutputKind::Image if cfg.heatmap && value.rank() == 2 => {
+            if let Some(heatmap) = render_heatmap(&value) {
+                let small = heatmap.width().min(heatmap.height()) < MIN_AUTO_IMAGE_DIM as u32;
+                let heatmap = match small {
+                    true => upscale(heatmap, cfg.render_scale),
+                    false => heatmap,
+                };
+                if let Some(items) = image_outputs(heatmap, cfg) {
+                    return items;
+                }
+            }
+        }
+        OutputKind::Image if is_animation(&value) => {
+            if let Some(items) = gif_outputs(&value, cfg) {
+                return items;
+            }
+        }
+        OutputKind::Image => {
+            if let Ok(image) = value_to_image(&value) {
+                let small = image.width().min(image.height()) < MIN_AUTO_IMAGE_DIM as u32;
+                let items = match cfg.small_image_mode {
+                    SmallImageMode::AsciiArt if small => {
+                        Some(vec![OutputItem::Note(image_to_ascii(&image))])
+                    }
+                    SmallImageMode::Upscale if small => {
+                        image_outputs(upscale(image, cfg.render_scale), cfg)
+                    }
+                    _ => image_outputs(image, cfg),
+                };
+                if let Some(items) = items {
+                    return items;
                 }
             }
         }
+        OutputKind::Misc => {}
+    }
+
+    vec![OutputItem::Misc(value)]
+}
+
+/// Draws a numeric matrix with each element colored by where it falls between the smallest and
+/// largest element, through the viridis colormap. Elements that aren't finite are transparent.
+/// Returns `None` for anything else, or for matrices over [`MAX_HEATMAP_DIM`] on either side.
+pub fn render_heatmap(value: &uiua::Value) -> Option<image::DynamicImage> {
+    let data: Vec<f64> = match value {
+        uiua::Value::Num(arr) => arr.elements().copied().collect(),
+        uiua::Value::Byte(arr) => arr.elements().map(|&b| b as f64).collect(),
+        _ => return None,
+    };
+    let [height @ 1..=MAX_HEATMAP_DIM, width @ 1..=MAX_HEATMAP_DIM] = *value.shape().dims() else {
+        return None;
+    };
+    let finite = data.iter().copied().filter(|x| x.is_finite());
+    let min = finite.clone().fold(f64::INFINITY, f64::min);
+    let max = finite.fold(f64::NEG_INFINITY, f64::max);
+
+    let mut heatmap = image::RgbaImage::new(width as u32, height as u32);
+    for (pixel, &x) in heatmap.pixels_mut().zip(&data) {
+        if !x.is_finite() {
+            continue;
+        }
+        let t = if max > min {
+            (x - min) / (max - min)
+        } else {
+            0.5
+        };
+        let position = t * (VIRIDIS.len() - 1) as f64;
+        let (low, high) = (
+            VIRIDIS[position.floor() as usize],
+            VIRIDIS[position.ceil() as usize],
+        );
+        let mix = position.fract();
+        let channel = |i: usize| (low[i] as f64 + (high[i] as f64 - low[i] as f64) * mix) as u8;
+        *pixel = image::Rgba([channel(0), channel(1), channel(2), 255]);
+    }
+    Some(image::DynamicImage::ImageRgba8(heatmap))
+}
+
+/// Encodes an image to be sent, noting if it had to be shrunk to fit
+fn image_outputs(image: image::DynamicImage, cfg: &RunConfig) -> Option<Vec<OutputItem>> {
+    let (bytes, downscaled) =
+        encode_png_within(image, cfg.max_image_bytes, cfg.png_compression).ok()?;
+    let mut items = vec![OutputItem::Image(bytes.into())];
+    if downscaled {
+        items.push(OutputItem::Note("(downscaled)".into()));
+    }
+    Some(items)
+}
+
+/// Scales the image up by the smallest whole factor that gets both sides to the automatic image size
+/// times `scale`
+fn upscale(image: image::DynamicImage, scale: f32) -> image::DynamicImage {
+    let shortest = image.width().min(image.height()).max(1);
+    let target = ((MIN_AUTO_IMAGE_DIM as f32 * scale.max(1.0)).round() as u32).max(1);
+    let factor = target.div_ceil(shortest);
+    image.resize_exact(
+        image.width() * factor,
+        image.height() * factor,
+        image::imageops::FilterType::Nearest,
+    )
+}
+
+/// Draws the image as text, one character per pixel, going from dark to bright
+fn image_to_ascii(image: &image::DynamicImage) -> String {
+    const RAMP: &[u8] = b" .:-=+*#%@";
+
+    let luma = image.to_luma_alpha8();
+    let mut art = String::new();
+    for row in luma.rows() {
+        for &image::LumaA([brightness, alpha]) in row {
+            let level = brightness as usize * alpha as usize / 255;
+            art.push(RAMP[level * (RAMP.len() - 1) / 255] as char);
+        }
+        art.push('\n');
+    }
+    art.pop();
+    art
+}
+
+/// Scratch space for audio encoding, kept around so busy channels don't reallocate it every clip
+#[derive(Default)]
+struct AudioBuffers {
+    channels: Vec<Vec<f32>>,
+    sink: Vec<u8>,
+}
+
+/// Buffers not in use right now. Shared rather than per thread, since values are rendered on
+/// short-lived threads.
+static AUDIO_BUFFERS: Mutex<Vec<AudioBuffers>> = Mutex::new(Vec::new());
+
+/// Like uiua's `value_to_audio_channels`, but without its limit of 5 channels
+fn audio_channels(value: &uiua::Value) -> Result<Vec<Vec<f64>>, String> {
+    let samples: Vec<f64> = match value {
+        uiua::Value::Num(arr) if arr.rank() == 2 => arr.elements().copied().collect(),
+        uiua::Value::Byte(arr) if arr.rank() == 2 => arr.elements().map(|&b| b as f64).collect(),
+        _ => return uiua::encode::value_to_audio_channels(value),
+    };
+    Ok(samples
+        .chunks_exact(value.row_len().max(1))
+        .map(<[f64]>::to_vec)
+        .collect())
+}
+
+/// Averages the channels down to `channels` of them, channel `i` going into `i % channels`. For
+/// stereo that's the even channels on the left and the odd ones on the right.
+fn downmix(samples: &[Vec<f64>], channels: usize) -> Vec<Vec<f64>> {
+    let len = samples.iter().map(Vec::len).max().unwrap_or(0);
+    let mut mixed = vec![vec![0.0; len]; channels.min(samples.len())];
+    for (i, mix) in mixed.iter_mut().enumerate() {
+        let sources: Vec<&Vec<f64>> = samples.iter().skip(i).step_by(channels).collect();
+        for source in &sources {
+            for (out, sample) in mix.iter_mut().zip(source.iter()) {
+                *out += sample / sources.len() as f64;
+            }
+        }
+    }
+    mixed
+}
+
+/// Encodes the channels as OGG Vorbis
+fn encode_ogg(
+    samples: &[Vec<f64>],
+    sample_rate: u32,
+) -> Result<Box<[u8]>, Box<dyn std::error::Error>> {
+    let take = || AUDIO_BUFFERS.lock().ok()?.pop();
+    let mut buffers = take().unwrap_or_default();
+    let result = encode_ogg_with(&mut buffers, samples, sample_rate);
+    if let Ok(mut pool) = AUDIO_BUFFERS.lock() {
+        pool.push(buffers);
+    }
+    result
+}
+
+fn encode_ogg_with(
+    AudioBuffers { channels, sink }: &mut AudioBuffers,
+    samples: &[Vec<f64>],
+    sample_rate: u32,
+) -> Result<Box<[u8]>, Box<dyn std::error::Error>> {
+    channels.resize_with(samples.len(), Vec::new);
+    for (channel, source) in channels.iter_mut().zip(samples) {
+        channel.clear();
+        channel.extend(source.iter().map(|&x| x as f32));
+    }
+    sink.clear();
+
+    let mut encoder = vorbis_rs::VorbisEncoderBuilder::new(
+        std::num::NonZeroU32::new(sample_rate).ok_or("The sample rate can't be 0")?,
+        std::num::NonZeroU8::new(samples.len() as u8).ok_or("unreachable")?,
+        &mut *sink,
+    )?
+    .build()?;
+    encoder.encode_audio_block(&channels[..samples.len()])?;
+    encoder.finish()?;
+    Ok(sink.as_slice().into())
+}
+
+/// What an audio value holds, worked out without encoding it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioInfo {
+    pub channels: usize,
+    /// Samples in each channel
+    pub samples: usize,
+    pub duration: Duration,
+}
+
+impl std::fmt::Display for AudioInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let channels = match self.channels {
+            1 => "mono".to_string(),
+            2 => "stereo".to_string(),
+            n => format!("{n} channels"),
+        };
+        write!(f, "🔊 {:.1}s {channels}", self.duration.as_secs_f64())
+    }
+}
+
+/// Describes the value as audio, or returns `None` if it wouldn't be sent as audio
+pub fn audio_info(value: &uiua::Value) -> Option<AudioInfo> {
+    audio_info_with_config(value, &RunConfig::default())
+}
+
+/// Like [`audio_info`], with the duration worked out from the config's sample rate
+pub fn audio_info_with_config(value: &uiua::Value, cfg: &RunConfig) -> Option<AudioInfo> {
+    if classify_value(value, cfg) != OutputKind::Audio || cfg.sample_rate == 0 {
+        return None;
+    }
+    let channels = audio_channels(value).ok()?;
+    let samples = channels.first().map_or(0, Vec::len);
+    Some(AudioInfo {
+        channels: channels.len(),
+        samples,
+        duration: Duration::from_secs_f64(samples as f64 / cfg.sample_rate as f64),
+    })
+}
+
+/// Silence put between clips when all the audio on the stack is played as one
+const CONCATENATED_AUDIO_GAP: Duration = Duration::from_millis(250);
+
+/// Joins the clips one after the other with `gap` of silence between them, then encodes them as a
+/// single OGG at uiua's default sample rate. Mono clips are copied to every channel when others
+/// have more, and any other missing channels are left silent. Returns `None` if any of the values
+/// isn't audio or if there's nothing to join.
+pub fn concat_audio(values: &[&uiua::Value], gap: Duration) -> Option<Vec<u8>> {
+    concat_audio_with_config(values, gap, &RunConfig::default())
+}
+
+fn concat_audio_with_config(
+    values: &[&uiua::Value],
+    gap: Duration,
+    cfg: &RunConfig,
+) -> Option<Vec<u8>> {
+    let samples = concat_channels(values, gap, cfg)?;
+    encode_ogg(&samples, cfg.sample_rate).ok().map(Vec::from)
+}
+
+fn concat_channels(
+    values: &[&uiua::Value],
+    gap: Duration,
+    cfg: &RunConfig,
+) -> Option<Vec<Vec<f64>>> {
+    let clips: Vec<Vec<Vec<f64>>> = (values.iter())
+        .map(|value| {
+            (classify_value(value, cfg) == OutputKind::Audio)
+                .then(|| audio_channels(value).ok())
+                .flatten()
+        })
+        .collect::<Option<_>>()?;
+    let channel_count = clips.iter().map(Vec::len).max()?;
+    let silence = (gap.as_secs_f64() * cfg.sample_rate as f64).round() as usize;
+
+    let mut joined = vec![Vec::new(); channel_count];
+    for (i, clip) in clips.iter().enumerate() {
+        if i > 0 {
+            joined
+                .iter_mut()
+                .for_each(|channel| channel.resize(channel.len() + silence, 0.0));
+        }
+        let length = clip.first().map_or(0, Vec::len);
+        for (c, channel) in joined.iter_mut().enumerate() {
+            match clip.as_slice() {
+                [mono] => channel.extend(mono),
+                _ => match clip.get(c) {
+                    Some(source) => channel.extend(source),
+                    None => channel.resize(channel.len() + length, 0.0),
+                },
+            }
+        }
+    }
+    Some(joined)
+}
+
+/// Size of the plot drawn for each audio channel
+const WAVEFORM_WIDTH: u32 = 600;
+const WAVEFORM_CHANNEL_HEIGHT: u32 = 100;
+
+/// Plots each channel's samples as a waveform, one above the other. Each column of the plot
+/// covers a slice of samples and spans from the lowest to the highest of them.
+fn render_waveform(samples: &[Vec<f64>]) -> image::DynamicImage {
+    let background = image::Rgb([0x1e, 0x1e, 0x2e]);
+    let line = image::Rgb([0x89, 0xdc, 0xeb]);
+    let height = WAVEFORM_CHANNEL_HEIGHT * samples.len() as u32;
+    let mut plot = image::RgbImage::from_pixel(WAVEFORM_WIDTH, height, background);
+
+    let half = (WAVEFORM_CHANNEL_HEIGHT / 2) as f64;
+    let to_row = |sample: f64| (half - sample.clamp(-1.0, 1.0) * (half - 1.0)) as u32;
+    for (i, channel) in samples.iter().enumerate() {
+        let top = i as u32 * WAVEFORM_CHANNEL_HEIGHT;
+        let per_column = channel.len().div_ceil(WAVEFORM_WIDTH as usize).max(1);
+        for (x, chunk) in channel.chunks(per_column).enumerate() {
+            let (lo, hi) = chunk
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &s| {
+                    (lo.min(s), hi.max(s))
+                });
+            for y in to_row(hi)..=to_row(lo) {
+                plot.put_pixel(x as u32, top + y.min(WAVEFORM_CHANNEL_HEIGHT - 1), line);
+            }
+        }
+    }
+    plot.into()
+}
+
+/// Size of the strip drawn by [`render_audio_thumbnail`]
+const AUDIO_THUMBNAIL_WIDTH: u32 = 200;
+const AUDIO_THUMBNAIL_HEIGHT: u32 = 40;
+
+/// Draws the loudest any channel gets over each slice of the clip as a bar centered on the strip,
+/// so all the channels together make one envelope
+fn render_audio_thumbnail(samples: &[Vec<f64>]) -> image::DynamicImage {
+    let background = image::Rgb([0x1e, 0x1e, 0x2e]);
+    let bar = image::Rgb([0x89, 0xdc, 0xeb]);
+    let mut strip =
+        image::RgbImage::from_pixel(AUDIO_THUMBNAIL_WIDTH, AUDIO_THUMBNAIL_HEIGHT, background);
+
+    let len = samples.iter().map(Vec::len).max().unwrap_or(0);
+    let per_column = len.div_ceil(AUDIO_THUMBNAIL_WIDTH as usize).max(1);
+    let half = AUDIO_THUMBNAIL_HEIGHT / 2;
+    for x in 0..AUDIO_THUMBNAIL_WIDTH.min(len.div_ceil(per_column) as u32) {
+        let start = x as usize * per_column;
+        let peak = (samples.iter())
+            .flat_map(|channel| channel.iter().skip(start).take(per_column))
+            .fold(0.0, |peak: f64, s| peak.max(s.abs()));
+        // At least a pixel, so quiet parts still show up as part of the clip
+        let reach = ((peak.min(1.0) * half as f64).round() as u32).clamp(1, half);
+        for y in half - reach..half + reach {
+            strip.put_pixel(x, y, bar);
+        }
+    }
+    strip.into()
+}
+
+/// Encodes the image as a PNG, trading size for speed as asked
+fn encode_png(image: &image::DynamicImage, speed: PngSpeed) -> Result<Vec<u8>, String> {
+    use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+    use image::{ColorType, ImageEncoder};
+
+    let (compression, filter) = match speed {
+        PngSpeed::Fast => (CompressionType::Fast, FilterType::Sub),
+        PngSpeed::Default => (CompressionType::Default, FilterType::Adaptive),
+        PngSpeed::Best => (CompressionType::Best, FilterType::Adaptive),
+    };
+    let converted;
+    let image = match image.color() {
+        ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8 => image,
+        _ => {
+            converted = image::DynamicImage::from(image.to_rgba8());
+            &converted
+        }
+    };
+    let mut bytes = Vec::new();
+    PngEncoder::new_with_quality(&mut bytes, compression, filter)
+        .write_image(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color(),
+        )
+        .map_err(|e| format!("Failed to encode image: {e}"))?;
+    Ok(bytes)
+}
 
-        Self::Misc(value)
+/// Encodes the image as a PNG, shrinking it until it fits in `budget` bytes or gets too small to
+/// shrink any further. Also returns whether any shrinking happened.
+fn encode_png_within(
+    mut image: image::DynamicImage,
+    budget: usize,
+    speed: PngSpeed,
+) -> Result<(Vec<u8>, bool), String> {
+    let mut bytes = encode_png(&image, speed)?;
+    let mut downscaled = false;
+    while bytes.len() > budget {
+        // PNG size grows roughly with the pixel count, so shrink both sides by the square root
+        let scale = (budget as f64 / bytes.len() as f64).sqrt().clamp(0.5, 0.9);
+        let width = (image.width() as f64 * scale) as u32;
+        let height = (image.height() as f64 * scale) as u32;
+        if width < MIN_AUTO_IMAGE_DIM as u32 || height < MIN_AUTO_IMAGE_DIM as u32 {
+            trace!(size = bytes.len(), "Image can't be shrunk any further");
+            break;
+        }
+        trace!(
+            size = bytes.len(),
+            width,
+            height,
+            "Downscaling oversized image"
+        );
+        image = image.resize_exact(width, height, image::imageops::FilterType::Triangle);
+        bytes = encode_png(&image, speed)?;
+        downscaled = true;
     }
+    Ok((bytes, downscaled))
 }
 
+/// Runs the code and renders what it left behind. Anything the code throws, and every other way
+/// of failing, comes back as `Err`, so an `Ok` only ever holds what the code produced, even when
+/// that's a string saying "error". The one exception is running out of time after getting
+/// somewhere, which is `Ok` with the partial result and a note saying so at the end.
 pub fn run_uiua(code: &str) -> Result<Vec<OutputItem>, String> {
+    run_uiua_with_config(code, &RunConfig::default())
+}
+
+/// Whether the run failed, as opposed to succeeding with output that merely describes an error,
+/// see [`run_uiua`]
+pub fn is_error_result(result: &Result<Vec<OutputItem>, String>) -> bool {
+    result.is_err()
+}
+
+pub fn run_uiua_with_config(code: &str, cfg: &RunConfig) -> Result<Vec<OutputItem>, String> {
+    run_uiua_with_inputs(code, &[], cfg)
+}
+
+/// Runs the code with the inputs already on the stack, the last one on top
+pub fn run_uiua_with_inputs(
+    code: &str,
+    inputs: &[uiua::Value],
+    cfg: &RunConfig,
+) -> Result<Vec<OutputItem>, String> {
+    let mut result = collect_outputs(code, inputs, cfg);
+    if let (Ok(items), true) = (&mut result, cfg.stack_board) {
+        if let Some(board) = render_stack_board_with_config(items, cfg) {
+            *items = vec![OutputItem::Image(board.into())];
+        }
+    }
+    if let Ok(items) = &result {
+        let _span = tracing::trace_span!("outputs").entered();
+        trace!(produced = summarize_outputs(items), "Rendered outputs");
+    }
+    result
+}
+
+/// Whether the input is more likely an uploaded file than code, judging by NUL bytes, undecodable
+/// bytes, or a lot of control characters
+fn looks_binary(code: &str) -> bool {
+    let mut chars = 0;
+    let mut control = 0;
+    for c in code.chars() {
+        if c == '\0' || c == char::REPLACEMENT_CHARACTER {
+            return true;
+        }
+        chars += 1;
+        if c.is_control() && !matches!(c, '\n' | '\r' | '\t') {
+            control += 1;
+        }
+    }
+    control * 10 > chars
+}
+
+fn collect_outputs(
+    code: &str,
+    inputs: &[uiua::Value],
+    cfg: &RunConfig,
+) -> Result<Vec<OutputItem>, String> {
+    let code = strip_code_fences(code);
     trace!(code, "Starting to execute uiua code");
     if code.is_empty() {
         return Err("Cannot run empty code".into());
     }
+    if looks_binary(code) {
+        trace!("Input looked like binary data");
+        return Err("input doesn't look like Uiua source.".into());
+    }
 
-    let mut runtime = Uiua::with_safe_sys().with_execution_limit(DEFAULT_EXECUTION_LIMIT);
-    let exp_code = &format!("# Experimental!\n{code}");
-
-    match runtime.run_str(exp_code) {
-        Ok(_c) => {
+    match run_code(code, inputs, cfg) {
+        CodeRun {
+            stack,
+            error: None,
+            defined,
+            printed,
+            media,
+            ..
+        } => {
             trace!(code, "Code ran successfully");
-            let mut stack = runtime.take_stack();
-            let stack_len = stack.len();
-            if stack_len > MAX_STACK_VALS_DISPLAYED {
-                stack.truncate(MAX_STACK_VALS_DISPLAYED);
-            }
-            let results: Vec<_> = stack
-                .into_iter()
-                .map(|val| val.into())
-                .chain((stack_len > MAX_STACK_VALS_DISPLAYED).then(|| {
-                    OutputItem::Continuation((stack_len - MAX_STACK_VALS_DISPLAYED) as u32)
-                }))
-                .collect();
+            if stack.is_empty() && media.is_empty() && !printed && !defined.is_empty() {
+                // Nothing to show, so at least confirm the definitions went through
+                return Ok(vec![OutputItem::Note(format!(
+                    "defined: {}",
+                    defined.join(", ")
+                ))]);
+            }
+            let mut budget = MediaBudget::new(cfg);
+            let mut results = Vec::new();
+            budget.admit(media, &mut results);
+            results.extend(stack_to_outputs(stack, cfg, &mut budget));
+            if cfg.deprecation_warnings {
+                results.extend(deprecated_primitives_in(code).into_iter().map(|prim| {
+                    OutputItem::Note(format!(
+                        "warning: {} is deprecated",
+                        describe_primitive(prim)
+                    ))
+                }));
+            }
+            Ok(results)
+        }
+        CodeRun {
+            stack,
+            error: Some(e),
+            media,
+            interruption,
+            ..
+        } if (matches!(e.kind, UiuaErrorKind::Timeout(..))
+            || interruption == Some(Interruption::TimedOut))
+            && !(stack.is_empty() && media.is_empty()) =>
+        {
+            trace!(code, "Code timed out, showing what it got through");
+            let mut budget = MediaBudget::new(cfg);
+            let mut results = Vec::new();
+            budget.admit(media, &mut results);
+            results.extend(stack_to_outputs(stack, cfg, &mut budget));
+            results.push(OutputItem::Note("Timed out, showing partial result".into()));
             Ok(results)
         }
-        Err(e) => {
+        CodeRun {
+            error: Some(e),
+            interruption,
+            ..
+        } => {
             trace!(code, "Code ran Unsuccessfully");
-            return Err(format!("Error while running: {e} "));
+            Err(describe_error(&e, interruption))
         }
     }
 }
 
-pub fn get_docs(f: &str) -> String {
-    match Primitive::from_format_name(f)
-        .or_else(|| Primitive::from_glyph(f.chars().next().unwrap_or_default()))
-        .or_else(|| Primitive::from_name(f))
-    {
-        Some(docs) => {
-            let short = docs
-                .doc()
-                .short
-                .iter()
-                .map(print_doc_frag)
-                .map(|s| format!("## {s}"))
-                .collect::<Vec<String>>()
-                .join("\n");
-
-            let long = docs
-                .doc()
-                .lines
-                .iter()
-                .take(5)
-                .map(print_docs)
-                .collect::<Vec<String>>()
-                .join("\n");
-            format!("\n{short}\n\n\n{long}\n\n([More information](https://uiua.org/docs/{f}))")
+/// Turns an error from running code into the message users see. An assertion the code made
+/// itself is worded as such, rather than as something having gone wrong, and running out of
+/// arguments is explained in terms of how many values were needed. `interruption` is why the
+/// interrupt hook stopped the run, if it did.
+fn describe_error(e: &UiuaError, interruption: Option<Interruption>) -> String {
+    match &e.kind {
+        UiuaErrorKind::Throw(value, ..) => format!("assertion failed: {}", value.format()),
+        UiuaErrorKind::Run(message, inputs) => {
+            describe_underflow(&message.value, &message.span, inputs)
+                .unwrap_or_else(|| format!("Error while running: {e} "))
         }
-        None => format!("No docs found for '{f}', did you spell it right?"),
+        UiuaErrorKind::Parse(errors, inputs) => (errors.first())
+            .and_then(|error| describe_unclosed(error, inputs))
+            .unwrap_or_else(|| format!("Error while running: {e} ")),
+        UiuaErrorKind::Interrupted => match interruption {
+            Some(Interruption::RanAway) => "output growing without bound.".into(),
+            Some(Interruption::TimedOut) => {
+                "Error while running: Maximum execution time exceeded ".into()
+            }
+            None => format!("Error while running: {e} "),
+        },
+        _ => format!("Error while running: {e} "),
     }
 }
 
-fn print_doc_frag(frag: &PrimDocFragment) -> String {
-    match frag {
-        PrimDocFragment::Text(t) => t.clone(),
-        PrimDocFragment::Code(t) => format!("`{t}`"),
-        PrimDocFragment::Emphasis(t) => format!("_{t}_"),
-        PrimDocFragment::Strong(t) => format!("**{t}**"),
-        PrimDocFragment::Primitive { prim, named } => {
-            if *named {
-                format!("{} `{}`", print_emoji(&prim), prim.name())
-            } else {
-                print_emoji(&prim)
-            }
+/// How uiua's message for running out of arguments starts, before the number of the argument
+const UNDERFLOW_PREFIX: &str = "Stack was empty when evaluating argument ";
+
+/// Rewords uiua's "Stack was empty when evaluating argument N" for whatever the span points at
+fn describe_underflow(message: &str, span: &uiua::Span, inputs: &uiua::Inputs) -> Option<String> {
+    let missing: usize = message.strip_prefix(UNDERFLOW_PREFIX)?.parse().ok()?;
+    let had = missing.checked_sub(1)?;
+    let uiua::Span::Code(code_span) = span else {
+        return None;
+    };
+    let name = code_span.try_as_str(inputs, str::to_string)?;
+    let function = match resolve_primitive(&name) {
+        Some(prim) => {
+            let needed = prim.args().unwrap_or(missing);
+            format!("{} needs {needed} values", describe_primitive(prim))
         }
-        PrimDocFragment::Link { text, url } => format!("[{text}]({url})"),
-    }
+        None => format!("`{name}` needs at least {missing} values"),
+    };
+    let values = if had == 1 { "value" } else { "values" };
+    Some(format!(
+        "{function}, but the stack only had {had} {values} (at {span})"
+    ))
 }
 
-fn print_docs(line: &PrimDocLine) -> String {
-    match line {
-        PrimDocLine::Text(vs) => vs
-            .into_iter()
-            .map(print_doc_frag)
-            .collect::<Vec<String>>()
-            .join(" "),
-        PrimDocLine::Example(e) => {
-            let out = match e.output().as_ref().map(|vs| vs.join(";")) {
-                Ok(l) => l,
-                Err(l) => l.to_string(),
+/// Rewords uiua's "Expected '\"'" and "Expected term or `]`" for whichever string or bracket was
+/// left open, pointing at where it was opened
+fn describe_unclosed(error: &Sp<ParseError>, inputs: &uiua::Inputs) -> Option<String> {
+    use uiua::AsciiToken::*;
+    let span = &error.span;
+    let (open, close, opened_at) = match &error.value {
+        // Unclosed strings are reported where they start
+        ParseError::Lex(LexError::ExpectedCharacter(chars)) if chars[..] == ['"'] => {
+            ('"', '"', span.start)
+        }
+        ParseError::Expected(expected, _) => {
+            // uiua doesn't export what's expected, only how it's shown
+            let close =
+                expected
+                    .iter()
+                    .find_map(|expectation| match expectation.to_string().as_str() {
+                        "`]`" => Some(CloseBracket),
+                        "`)`" => Some(CloseParen),
+                        "`}`" => Some(CloseCurly),
+                        _ => None,
+                    })?;
+            // Brackets are reported where the closing one was expected, so find the opening one
+            let code = inputs.get(&span.src);
+            let (tokens, ..) = uiua::lex(&code, (), &mut uiua::Inputs::default());
+            let mut open = Vec::new();
+            for token in tokens {
+                if token.span.start.byte_pos >= span.start.byte_pos {
+                    break;
+                }
+                match token.value {
+                    Token::Simple(OpenBracket | OpenParen | OpenCurly) => open.push(token),
+                    Token::Simple(CloseBracket | CloseParen | CloseCurly) => drop(open.pop()),
+                    _ => {}
+                }
+            }
+            let opened = open.pop()?;
+            let pair = match (&opened.value, close) {
+                (Token::Simple(OpenBracket), CloseBracket) => ('[', ']'),
+                (Token::Simple(OpenParen), CloseParen) => ('(', ')'),
+                (Token::Simple(OpenCurly), CloseCurly) => ('{', '}'),
+                _ => return None,
             };
-            let text = format!(
-                "{}\n{}",
-                e.input(),
-                out.lines().map(|l| format!("# {l}\n")).collect::<String>()
-            );
+            (pair.0, pair.1, opened.span.start)
+        }
+        _ => return None,
+    };
+    Some(format!(
+        "unclosed `{open}` started at line {} — did you forget a `{close}`?",
+        opened_at.line
+    ))
+}
+
+/// Runs the code and returns the values it left on the stack, bottom first, without encoding them
+pub fn eval_uiua(code: &str, cfg: &RunConfig) -> Result<Vec<uiua::Value>, String> {
+    eval_with_inputs(code, &[], cfg)
+}
+
+fn eval_with_inputs(
+    code: &str,
+    inputs: &[uiua::Value],
+    cfg: &RunConfig,
+) -> Result<Vec<uiua::Value>, String> {
+    let code = strip_code_fences(code);
+    if code.is_empty() {
+        return Err("Cannot run empty code".into());
+    }
+    match run_code(code, inputs, cfg) {
+        CodeRun {
+            stack, error: None, ..
+        } => Ok(stack),
+        CodeRun {
+            error: Some(e),
+            interruption,
+            ..
+        } => Err(describe_error(&e, interruption)),
+    }
+}
 
-            format!("{}", highlight_code(&text))
+/// Hashes what the code leaves on the stack for each set of inputs, see
+/// [`run_uiua_with_inputs`], so programs that behave the same get the same fingerprint however
+/// they're written. Randomness and the time are fixed so they can't tell runs apart. Fails if the
+/// code fails on any of the inputs.
+///
+/// The hash is 64-bit FNV-1a over each value as [`uiua::Value::show`] writes it, so fingerprints
+/// stay the same across builds and Rust versions and can be stored.
+pub fn behavior_fingerprint(code: &str, probe_inputs: &[Vec<uiua::Value>]) -> Result<u64, String> {
+    let cfg = RunConfig {
+        seed: Some(0),
+        fixed_time: Some(0.0),
+        ..RunConfig::default()
+    };
+    let mut hash = Fnv1a::default();
+    for (i, inputs) in probe_inputs.iter().enumerate() {
+        let stack = eval_with_inputs(code, inputs, &cfg)
+            .map_err(|e| format!("With input set {}: {e}", i + 1))?;
+        for value in stack {
+            hash.write(value.show().as_bytes());
+            // Never in UTF-8, so values can't run into each other
+            hash.write(&[0xff]);
         }
+        hash.write(&[0xfe]);
     }
+    Ok(hash.0)
 }
 
-fn print_emoji(c: &Primitive) -> String {
-    if c.is_experimental() {
-        c.names()
-            .glyph
-            .map(|g| g.to_string())
-            .unwrap_or(c.name().to_string())
-    } else {
-        let spaceless_name = c.name().split(' ').collect::<String>();
-        if let Some(id) = EMOJI_MAP.get(&*spaceless_name) {
-            //format!("<:{}:{}>", spaceless_name, id) // For when the bot has it internally
-            format!(":{}:", spaceless_name)
-        } else {
-            format!("<{}>", spaceless_name)
+/// The 64-bit FNV-1a hash, see <http://www.isthe.com/chongo/tech/comp/fnv/>
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
         }
     }
 }
 
-pub fn format_and_get_pad_link(code: &str) -> String {
-    let config = FormatConfig::default();
-    let formatted = format_str(code, &config).unwrap().output;
+/// Runs both `code` and `expected`, and checks whether they leave the same value on top of the
+/// stack
+pub fn run_and_expect(code: &str, expected: &str) -> Result<bool, String> {
+    let cfg = RunConfig::default();
+    let top = |code: &str, which: &str| {
+        eval_uiua(code, &cfg)
+            .map_err(|e| format!("The {which} failed: {e}"))?
+            .pop()
+            .ok_or_else(|| format!("The {which} left nothing on the stack"))
+    };
+    Ok(top(code, "code")? == top(expected, "expected value")?)
+}
 
-    let encoded = URL_SAFE.encode(code);
-    let link = format!("https://www.uiua.org/pad?src={}__{encoded}", uiua::VERSION);
+/// Runs the code one top-level statement at a time, like cells in a notebook, and returns each
+/// statement's source along with what it added to the stack and showed through the backend
+pub fn run_uiua_per_statement(code: &str) -> Result<Vec<(String, Vec<OutputItem>)>, String> {
+    let code = strip_code_fences(code);
+    if code.is_empty() {
+        return Err("Cannot run empty code".into());
+    }
+    if looks_binary(code) {
+        return Err("input doesn't look like Uiua source.".into());
+    }
+    let cfg = RunConfig::default();
+    let statements = run_statements(code, &cfg)?;
+    let mut budget = MediaBudget::new(&cfg);
+    let mut results = Vec::new();
+    for statement in statements {
+        let mut items = Vec::new();
+        let images = (statement.images.into_iter())
+            .filter_map(|image| image_outputs(image, &cfg))
+            .flatten();
+        budget.admit(images, &mut items);
+        items.extend(stack_to_outputs(statement.values, &cfg, &mut budget));
+        results.push((statement.source, items));
+    }
+    Ok(results)
+}
+
+/// Like [`run_uiua_with_config`], but runs and encodes on tokio's blocking thread pool so the
+/// async runtime can keep handling other messages in the meantime
+pub async fn run_uiua_async(code: String, cfg: RunConfig) -> Result<Vec<OutputItem>, String> {
+    tokio::task::spawn_blocking(move || run_uiua_with_config(&code, &cfg))
+        .await
+        .map_err(|e| format!("Error while running: {e} "))?
+}
+
+/// The stack right after one word of the code ran
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackSnapshot {
+    /// The word that just ran, as written
+    pub word: String,
+    /// The whole stack at that point, bottom first
+    pub stack: Vec<uiua::Value>,
+}
 
-    format!("[pad]({link}) for: {}", highlight_code(&formatted))
+/// Records the stack after each top-level word of the code, in the order they run, which is right
+/// to left within a line. A modifier counts as one word along with its functions, and bindings and
+/// statements spanning several lines count as one word. uiua has no hook for watching each step,
+/// so the words of a line are compiled and run one at a time, each once, on the same runtime.
+/// Gives up after [`MAX_TRACE_SNAPSHOTS`] words.
+pub fn run_uiua_trace(code: &str) -> Result<Vec<StackSnapshot>, String> {
+    use uiua::ast::{Item, Word};
+
+    let code = strip_code_fences(code);
+    if code.is_empty() {
+        return Err("Cannot run empty code".into());
+    }
+    if looks_binary(code) {
+        return Err("input doesn't look like Uiua source.".into());
+    }
+
+    let cfg = RunConfig::default();
+    let deadline = Instant::now() + cfg.execution_limit;
+    let (mut run, error) = RunStart::new(&cfg);
+    let mut result = error.map_or(Ok(()), Err);
+    // Compile-time evaluation doesn't play well with compiling word by word
+    run.compiler.pre_eval_mode(PreEvalMode::Lazy);
+
+    let mut snapshots = Vec::new();
+    'statements: for (line, statement) in split_statements(code) {
+        if result.is_err() {
+            break;
+        }
+        let (items, _, _) = uiua::parse(statement, (), &mut uiua::Inputs::default());
+        // Where each word starts and ends within the statement, leftmost first
+        let words: Vec<(usize, usize)> = match &items[..] {
+            // Lines broken up with `'` or `;;` don't run right to left, so they're one word
+            [Item::Words(lines)]
+                if lines.len() == 1
+                    && !(lines[0].iter())
+                        .any(|word| matches!(word.value, Word::BreakLine | Word::FlipLine)) =>
+            {
+                (lines[0].iter())
+                    .filter(|word| {
+                        !matches!(
+                            word.value,
+                            Word::Spaces
+                                | Word::Comment(_)
+                                | Word::SemanticComment(_)
+                                | Word::OutputComment { .. }
+                        )
+                    })
+                    .map(|word| (word.span.start.byte_pos as usize, word.span.end.byte_pos))
+                    .map(|(start, end)| (start, end as usize))
+                    .collect()
+            }
+            _ => vec![(0, statement.trim_end().len())],
+        };
+
+        for &(start, end) in words.iter().rev() {
+            if snapshots.len() == MAX_TRACE_SNAPSHOTS {
+                break 'statements;
+            }
+            run.runtime = std::mem::take(&mut run.runtime)
+                .with_execution_limit(deadline.saturating_duration_since(Instant::now()));
+            // Padded so errors point at the right line and column
+            let padded = format!(
+                "{}{}{}",
+                "\n".repeat(line),
+                " ".repeat(statement[..start].chars().count()),
+                &statement[start..end]
+            );
+            result = match run.compiler.load_str(&padded) {
+                Ok(_) => run.runtime.run_compiler(&mut run.compiler),
+                Err(e) => Err(e),
+            };
+            if result.is_err() {
+                break 'statements;
+            }
+            snapshots.push(StackSnapshot {
+                word: statement[start..end].to_string(),
+                stack: run.runtime.stack().to_vec(),
+            });
+        }
+    }
+
+    let interruption = run.interruption();
+    run.finish(&cfg, result.is_ok());
+    result.map_err(|e| describe_error(&e, interruption))?;
+    Ok(snapshots)
+}
+
+/// What's left after running code
+struct CodeRun {
+    /// The final stack, or if there was an error, whatever uiua left of it
+    stack: Vec<uiua::Value>,
+    error: Option<UiuaError>,
+    /// Names of the bindings the code made, in the order they were defined, each function's with
+    /// its signature
+    defined: Vec<String>,
+    /// Whether the code wrote anything to stdout or stderr
+    printed: bool,
+    /// What the code showed through the backend, ready to be sent before the stack
+    media: Vec<OutputItem>,
+    /// Why the interrupt hook stopped the code, if it did
+    interruption: Option<Interruption>,
+}
+
+/// What a single top-level statement did
+struct StatementResult {
+    source: String,
+    /// The values it added to the stack, bottom first
+    values: Vec<uiua::Value>,
+    images: Vec<image::DynamicImage>,
+}
+
+/// The user's code as it's run all at once, with experimental features turned on
+fn source_that_runs(code: &str) -> String {
+    format!("{EXPERIMENTAL_PRAGMA}{code}")
+}
+
+/// Takes back out what running adds to the code, leaving exactly what the user wrote, for showing
+/// code that ran. A pragma of the user's own stays.
+pub fn user_visible_source(code: &str) -> &str {
+    code.strip_prefix(EXPERIMENTAL_PRAGMA).unwrap_or(code)
+}
+
+/// Runs the code the way the pad does, all at once on a plain runtime, and shows the resulting
+/// stack with uiua's own formatting, bottom value first like the bot lists them. Meant for tests
+/// pinning that [`format_value`] doesn't drift from how uiua displays values.
+pub fn pad_equivalent_text(code: &str) -> Result<String, String> {
+    let mut runtime =
+        Uiua::with_safe_sys().with_execution_limit(RunConfig::default().execution_limit);
+    runtime
+        .run_str(&source_that_runs(strip_code_fences(code)))
+        .map_err(|e| describe_error(&e, None))?;
+    let shown: Vec<String> = runtime.take_stack().iter().map(|v| v.show()).collect();
+    Ok(shown.join("\n"))
+}
+
+/// Runs the code all at once, with the inputs on the stack first
+fn run_code(code: &str, inputs: &[uiua::Value], cfg: &RunConfig) -> CodeRun {
+    let (mut run, mut error) = RunStart::new(cfg);
+    for input in inputs {
+        run.runtime.push(input.clone());
+    }
+    let known_bindings = run.compiler.assembly().bindings.len();
+    let mut defined = Vec::new();
+    if error.is_none() {
+        // Loaded as its own input, so error locations are lines of the user's code
+        let result = match run.compiler.load_str(code) {
+            Ok(_) => {
+                let new_bindings = run.compiler.assembly().bindings[known_bindings..].iter();
+                defined = new_bindings
+                    .filter_map(|binding| {
+                        let (start, end) = (binding.span.start.byte_pos, binding.span.end.byte_pos);
+                        let name = code.get(start as usize..end as usize)?;
+                        // uiua has no function values, so a binding is the only way code can hold
+                        // onto one
+                        Some(match &binding.kind {
+                            uiua::BindingKind::Func(f) => {
+                                let sig = f.signature();
+                                format!("{name} (function |{}.{})", sig.args, sig.outputs)
+                            }
+                            _ => name.to_string(),
+                        })
+                    })
+                    .collect();
+                run.runtime.run_compiler(&mut run.compiler)
+            }
+            Err(e) => Err(e),
+        };
+        error = result.err();
+    }
+    // Whatever uiua left on the stack, which after an error may well be nothing
+    let stack = run.runtime.take_stack();
+    let interruption = run.interruption();
+    let (media, printed) = run.finish(cfg, error.is_none());
+    CodeRun {
+        stack,
+        error,
+        defined,
+        printed,
+        media,
+        interruption,
+    }
+}
+
+/// Runs the code one top-level statement at a time, returning what each one did. Stops at the
+/// first statement that fails.
+fn run_statements(code: &str, cfg: &RunConfig) -> Result<Vec<StatementResult>, String> {
+    let (mut run, error) = RunStart::new(cfg);
+    let mut result = error.map_or(Ok(()), Err);
+    // Compile-time evaluation doesn't play well with compiling statement by statement
+    run.compiler.pre_eval_mode(PreEvalMode::Lazy);
+    let mut statements = Vec::new();
+    for (line, statement) in split_statements(code) {
+        if result.is_err() {
+            break;
+        }
+        let before = run.runtime.stack().to_vec();
+        // Pad with newlines so that error locations are lines of the user's code
+        let padded = format!("{}{statement}", "\n".repeat(line));
+        result = match run.compiler.load_str(&padded) {
+            Ok(_) => run.runtime.run_compiler(&mut run.compiler),
+            Err(e) => Err(e),
+        };
+        let after = run.runtime.stack();
+        // Whatever the statement didn't touch at the bottom of the stack isn't new
+        let kept = (before.iter().zip(after))
+            .take_while(|(before, after)| before == after)
+            .count();
+        statements.push(StatementResult {
+            source: statement.trim_end().to_string(),
+            values: after[kept..].to_vec(),
+            images: run.backend.take_images(),
+        });
+    }
+    let interruption = run.interruption();
+    run.finish(cfg, result.is_ok());
+    result
+        .map(|_| statements)
+        .map_err(|e| describe_error(&e, interruption))
+}
+
+/// A runtime and compiler ready to run the user's code, and where they came from
+struct RunStart<'a> {
+    backend: Arc<BotBackend>,
+    runtime: Uiua,
+    compiler: Compiler,
+    template: Template,
+    /// The prelude to give the template back under, if it can be used again
+    reused_prelude: Option<&'a str>,
+    /// Set once the interrupt hook stops the run
+    interruption: Arc<OnceLock<Interruption>>,
+}
+
+impl<'a> RunStart<'a> {
+    /// Gets the runtime and compiler from an idle template when it can, returning the error if
+    /// loading the prelude failed
+    fn new(cfg: &'a RunConfig) -> (Self, Option<UiuaError>) {
+        if let Some(seed) = cfg.seed {
+            // uiua keeps its generator per thread, so it has to be seeded on the running thread
+            uiua::seed_random(seed);
+        }
+        // Modules could be imported by the prelude, and they can differ between runs
+        let reused_prelude =
+            (cfg.prelude.as_deref()).filter(|_| cfg.reuse_prelude && cfg.modules.is_empty());
+        let (template, error) = match reused_prelude.and_then(take_template) {
+            Some(template) => {
+                template.backend.reset(cfg);
+                (template, None)
+            }
+            None => set_up(cfg),
+        };
+        let mut runtime = template
+            .runtime
+            .clone()
+            .with_execution_limit(cfg.execution_limit);
+        let interruption = Arc::new(OnceLock::new());
+        if let Some(hook) = interrupt_hook(cfg, interruption.clone()) {
+            runtime = runtime.with_interrupt_hook(hook);
+        }
+        let start = RunStart {
+            backend: template.backend.clone(),
+            runtime,
+            compiler: template.compiler.clone(),
+            reused_prelude: reused_prelude.filter(|_| error.is_none()),
+            template,
+            interruption,
+        };
+        (start, error)
+    }
+
+    /// Why the interrupt hook stopped the run, if it has
+    fn interruption(&self) -> Option<Interruption> {
+        self.interruption.get().copied()
+    }
+
+    /// Takes what the run showed through the backend, and whether it printed anything, then puts
+    /// the template back if `succeeded`
+    fn finish(self, cfg: &RunConfig, succeeded: bool) -> (Vec<OutputItem>, bool) {
+        if cfg.seed.is_some() {
+            unseed_random();
+        }
+        let mut media: Vec<OutputItem> = (self.backend.take_images().into_iter())
+            .filter_map(|image| image_outputs(image, cfg))
+            .flatten()
+            .collect();
+        if self.backend.suppressed_media() > 0 {
+            media.push(OutputItem::Note(format!(
+                "{} media outputs suppressed",
+                self.backend.suppressed_media()
+            )));
+        }
+        let printed =
+            !self.backend.take_stdout().is_empty() || !self.backend.take_stderr().is_empty();
+        if let (true, Some(prelude)) = (succeeded, self.reused_prelude) {
+            give_back_template(prelude, self.template);
+        }
+        (media, printed)
+    }
+}
+
+/// A runtime and compiler that have loaded the pragma and run the prelude, for runs to start from
+/// copies of. The backend is shared with the copies, so only one run may use a template at once.
+struct Template {
+    backend: Arc<BotBackend>,
+    runtime: Uiua,
+    compiler: Compiler,
+}
+
+/// Idle templates for one prelude, along with when a run last used it
+type IdleTemplates = (Instant, Vec<Template>);
+
+/// Templates no run is using, by the prelude they ran
+static IDLE_TEMPLATES: LazyLock<Mutex<HashMap<String, IdleTemplates>>> =
+    LazyLock::new(Mutex::default);
+/// How many idle templates are kept for each prelude, about as many as run at once
+const MAX_IDLE_TEMPLATES: usize = 4;
+/// How many preludes idle templates are kept for, in case a lot of different ones are used. The
+/// one used longest ago makes room for a new one.
+const MAX_TEMPLATE_PRELUDES: usize = 8;
+
+/// Makes a template for the config's prelude, returning the error if loading it failed partway
+fn set_up(cfg: &RunConfig) -> (Template, Option<uiua::UiuaError>) {
+    let backend = Arc::new(BotBackend::new(cfg));
+    let mut runtime = Uiua::with_backend(backend.clone() as Arc<dyn SysBackend>);
+    let mut compiler = Compiler::with_backend(backend.clone() as Arc<dyn SysBackend>);
+    let mut error = compiler.load_str(EXPERIMENTAL_PRAGMA).err();
+    if let (None, Some(prelude)) = (&error, &cfg.prelude) {
+        // Loaded as its own input so the user's code keeps its line numbers
+        let result = match compiler.load_str(prelude) {
+            Ok(_) => runtime.run_compiler(&mut compiler),
+            Err(e) => Err(e),
+        };
+        error = result.err();
+    }
+    let template = Template {
+        backend,
+        runtime,
+        compiler,
+    };
+    (template, error)
+}
+
+// A run that panicked while holding the lock can't have left a template half put back, so the
+// pool is still fine to use
+fn take_template(prelude: &str) -> Option<Template> {
+    let mut idle = IDLE_TEMPLATES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let (last_used, templates) = idle.get_mut(prelude)?;
+    *last_used = Instant::now();
+    templates.pop()
+}
+
+fn give_back_template(prelude: &str, template: Template) {
+    let mut idle = IDLE_TEMPLATES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if idle.len() >= MAX_TEMPLATE_PRELUDES && !idle.contains_key(prelude) {
+        let oldest = (idle.iter())
+            .min_by_key(|(_, (last_used, _))| *last_used)
+            .map(|(oldest, _)| oldest.clone());
+        if let Some(oldest) = oldest {
+            idle.remove(&oldest);
+        }
+    }
+    let (last_used, templates) =
+        (idle.entry(prelude.to_string())).or_insert_with(|| (Instant::now(), Vec::new()));
+    *last_used = Instant::now();
+    if templates.len() < MAX_IDLE_TEMPLATES {
+        templates.push(template);
+    }
+}
+
+/// Why the interrupt hook stopped a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interruption {
+    /// The runaway guard saw the run allocate too much, see [`RunConfig::runaway_guard`]
+    RanAway,
+    /// The execution limit passed while the clock was fixed
+    TimedOut,
+}
+
+/// An interrupt hook for the checks uiua can't do itself, which records why it stopped the run
+/// in `interruption`. Returns `None` if there's nothing to check.
+///
+/// The runaway guard stops the run once the thread it runs on has allocated more than the limit
+/// and not freed it since the hook was made. It needs `CountingAllocator` to count with, and is
+/// off without it. uiua times runs with the backend's clock, which doesn't move while
+/// [`RunConfig::fixed_time`] is set, so the execution limit is kept to here then.
+fn interrupt_hook(
+    cfg: &RunConfig,
+    interruption: Arc<OnceLock<Interruption>>,
+) -> Option<impl Fn() -> bool + Send + Sync + 'static> {
+    let runaway = cfg.runaway_guard.and_then(|limit| {
+        let start = thread_live_bytes();
+        if start.is_none() {
+            static WARNED: Once = Once::new();
+            WARNED.call_once(|| {
+                warn!("The runaway guard is off, as CountingAllocator isn't the allocator")
+            });
+        }
+        Some((limit, start?))
+    });
+    let deadline = cfg.fixed_time.map(|_| Instant::now() + cfg.execution_limit);
+    if runaway.is_none() && deadline.is_none() {
+        return None;
+    }
+    Some(move || {
+        if let Some((limit, start)) = runaway {
+            let grown = thread_live_bytes().unwrap_or(start).saturating_sub(start);
+            if grown > limit as isize {
+                trace!(grown, limit, "Stopping a run that kept allocating");
+                let _ = interruption.set(Interruption::RanAway);
+                return true;
+            }
+        }
+        if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            let _ = interruption.set(Interruption::TimedOut);
+            return true;
+        }
+        false
+    })
+}
+
+#[cfg(feature = "counting-allocator")]
+fn thread_live_bytes() -> Option<isize> {
+    crate::allocation::thread_live_bytes()
+}
+
+#[cfg(not(feature = "counting-allocator"))]
+fn thread_live_bytes() -> Option<isize> {
+    None
+}
+
+/// Seeds uiua's generator for this thread from the time, so later runs on it aren't predictable
+fn unseed_random() {
+    uiua::seed_random(std::hash::BuildHasher::hash_one(
+        &std::collections::hash_map::RandomState::new(),
+        Instant::now(),
+    ));
+}
+
+/// Splits the code into its top-level statements, each paired with the line it starts on
+fn split_statements(code: &str) -> Vec<(usize, &str)> {
+    use uiua::ast::Item;
+
+    let (items, errors, _) = uiua::parse(code, (), &mut uiua::Inputs::default());
+    if !errors.is_empty() {
+        // Leave it to the compiler to report these
+        return vec![(0, code)];
+    }
+
+    let lines: Vec<&str> = code.split_inclusive('\n').collect();
+    let mut starts: Vec<usize> = items
+        .iter()
+        .flat_map(|item| match item {
+            Item::Words(lines) => (lines.iter())
+                .filter_map(|words| words.first())
+                .map(|word| word.span.start.line)
+                .collect(),
+            Item::Binding(binding) => vec![binding.name.span.start.line],
+            Item::Import(import) => vec![import.span().start.line],
+            Item::Module(module) => vec![module.span.start.line],
+            Item::Data(data) => vec![data.init_span.start.line],
+        })
+        .map(|line| line as usize - 1)
+        .collect();
+    // Comments right above a statement belong to it, which keeps doc comments with their bindings
+    for start in &mut starts {
+        while *start > 0 && lines[*start - 1].trim_start().starts_with('#') {
+            *start -= 1;
+        }
+    }
+    if let Some(first) = starts.first_mut() {
+        *first = 0;
+    }
+    starts.dedup();
+
+    let offsets: Vec<usize> = (lines.iter())
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some(start)
+        })
+        .chain([code.len()])
+        .collect();
+    let ends = starts.iter().skip(1).copied().chain([lines.len()]);
+    (starts.iter().zip(ends))
+        .map(|(&start, end)| (start, &code[offsets[start]..offsets[end]]))
+        .collect()
+}
+
+/// Keeps the media in one reply within [`RunConfig::max_total_media_bytes`]. The first media item
+/// that doesn't fit is replaced by a note, and every media item after it is left out.
+struct MediaBudget {
+    remaining: usize,
+    exhausted: bool,
+}
+
+impl MediaBudget {
+    fn new(cfg: &RunConfig) -> Self {
+        MediaBudget {
+            remaining: cfg.max_total_media_bytes,
+            exhausted: false,
+        }
+    }
+
+    /// Moves the items into `outputs`, leaving out media that goes over the budget
+    fn admit(
+        &mut self,
+        items: impl IntoIterator<Item = OutputItem>,
+        outputs: &mut Vec<OutputItem>,
+    ) {
+        for item in items {
+            let size = match &item {
+                OutputItem::Audio(bytes) | OutputItem::Image(bytes) | OutputItem::Gif(bytes) => {
+                    bytes.len()
+                }
+                _ => {
+                    outputs.push(item);
+                    continue;
+                }
+            };
+            if self.exhausted {
+                continue;
+            }
+            if size <= self.remaining {
+                self.remaining -= size;
+                outputs.push(item);
+            } else {
+                trace!(
+                    size,
+                    remaining = self.remaining,
+                    "Media went over the total size limit"
+                );
+                self.exhausted = true;
+                outputs.push(OutputItem::Note(
+                    "remaining outputs omitted (size limit)".into(),
+                ));
+            }
+        }
+    }
+}
+
+/// Converts the values left on the stack, cutting them off after [`MAX_STACK_VALS_DISPLAYED`] at
+/// the end [`RunConfig::truncate_keep`] doesn't keep.
+/// With [`RunConfig::collapse_duplicates`], runs of equal values are shown once with a count.
+fn stack_to_outputs(
+    stack: Vec<uiua::Value>,
+    cfg: &RunConfig,
+    budget: &mut MediaBudget,
+) -> Vec<OutputItem> {
+    let mut stack = stack;
+    let mut dropped = 0;
+    if cfg.top_only && stack.len() > 1 {
+        dropped = stack.len() - 1;
+        stack = stack.split_off(dropped);
+    }
+    let stack_len = stack.len();
+    let is_audio = |val: &uiua::Value| classify_value(val, cfg) == OutputKind::Audio;
+    let mut combined_audio = None;
+    if cfg.concat_audio {
+        let audio: Vec<&uiua::Value> = stack.iter().filter(|val| is_audio(val)).collect();
+        if audio.len() > 1 {
+            combined_audio = concat_audio_with_config(&audio, CONCATENATED_AUDIO_GAP, cfg)
+                .map(|bytes| OutputItem::Audio(bytes.into()));
+        }
+    }
+
+    // Each group also keeps how far from the top its topmost value was
+    let mut groups: Vec<(uiua::Value, usize, usize)> = Vec::new();
+    for (i, val) in stack.into_iter().enumerate() {
+        if cfg.drop_blank_outputs && is_blank_value(&val) {
+            continue;
+        }
+        if combined_audio.is_some() && is_audio(&val) {
+            continue;
+        }
+        let index = stack_len - 1 - i;
+        match groups.last_mut() {
+            Some((last, count, top)) if cfg.collapse_duplicates && *last == val => {
+                *count += 1;
+                *top = index;
+            }
+            _ => groups.push((val, 1, index)),
+        }
+    }
+    // The groups are in stack order, so the bottom comes first
+    let excess = groups.len().saturating_sub(MAX_STACK_VALS_DISPLAYED);
+    let (hidden_groups, trimmed) = match cfg.truncate_keep {
+        TruncateKeep::Bottom => (
+            groups.split_off(MAX_STACK_VALS_DISPLAYED.min(groups.len())),
+            StackEnd::Top,
+        ),
+        TruncateKeep::Top => (groups.drain(..excess).collect(), StackEnd::Bottom),
+    };
+    let hidden: usize = hidden_groups.iter().map(|(_, count, _)| count).sum();
+
+    let mut outputs = Vec::new();
+    budget.admit(combined_audio, &mut outputs);
+    render_values(groups, cfg, budget, &mut outputs);
+    outputs.extend((hidden > 0).then(|| OutputItem::Continuation(hidden as u32, trimmed)));
+    if cfg.count_dropped && dropped > 0 {
+        outputs.push(OutputItem::Continuation(dropped as u32, StackEnd::Bottom));
+    }
+    outputs
+}
+
+/// How many render threads may be alive at once across every run, counting the ones still busy
+/// with a value that timed out
+const MAX_RENDER_WORKERS: usize = 16;
+/// How many render threads are alive
+static RENDER_WORKERS: AtomicUsize = AtomicUsize::new(0);
+
+/// One of the [`MAX_RENDER_WORKERS`] places for a render thread, given back when dropped
+struct RenderSlot;
+
+impl RenderSlot {
+    fn take() -> Option<Self> {
+        RENDER_WORKERS
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |workers| {
+                (workers < MAX_RENDER_WORKERS).then_some(workers + 1)
+            })
+            .ok()
+            .map(|_| RenderSlot)
+    }
+}
+
+impl Drop for RenderSlot {
+    fn drop(&mut self) {
+        RENDER_WORKERS.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Values waiting for a render worker, with where they are in the output and how far from the
+/// top of the stack they were
+type RenderQueue = Mutex<VecDeque<(usize, uiua::Value, usize)>>;
+
+/// What a render worker says about the value at a place in the output
+enum RenderEvent {
+    Started(usize, Instant),
+    /// `None` for media left alone because the budget had already run out
+    Done(usize, Option<Vec<OutputItem>>),
+}
+
+/// Renders the values on a pool of at most [`RunConfig::render_threads`] workers, admitting each
+/// in stack order once everything before it is done. Running the code has its own limit, but a
+/// huge value can take as long to encode, so each value is given up on after
+/// [`RunConfig::render_timeout`]. A worker stuck on such a value still counts against the pool.
+fn render_values(
+    groups: Vec<(uiua::Value, usize, usize)>,
+    cfg: &RunConfig,
+    budget: &mut MediaBudget,
+    outputs: &mut Vec<OutputItem>,
+) {
+    let counts: Vec<usize> = groups.iter().map(|&(_, count, _)| count).collect();
+    let queue: Arc<RenderQueue> = Arc::new(Mutex::new(
+        (groups.into_iter().enumerate())
+            .map(|(pos, (val, _, index))| (pos, val, index))
+            .collect(),
+    ));
+    // Media that would only be left out isn't worth encoding
+    let skip_media = Arc::new(AtomicBool::new(budget.exhausted));
+    let (sender, events) = mpsc::channel();
+    let mut workers = 0;
+    let mut spawn_error = None;
+    for _ in 0..cfg.render_threads.max(1).min(counts.len()) {
+        match spawn_render_worker(queue.clone(), sender.clone(), skip_media.clone(), cfg) {
+            Ok(()) => workers += 1,
+            Err(e) => {
+                spawn_error = Some(e);
+                break;
+            }
+        }
+    }
+    drop(sender);
+
+    let mut rendered: Vec<Option<Option<Vec<OutputItem>>>> = counts.iter().map(|_| None).collect();
+    let mut started: HashMap<usize, Instant> = HashMap::new();
+    let mut next = 0;
+    while next < counts.len() {
+        if let Some(items) = rendered[next].take() {
+            if let Some(items) = items {
+                budget.admit(items, outputs);
+                if counts[next] > 1 {
+                    outputs.push(OutputItem::Note(format!("×{}", counts[next])));
+                }
+                skip_media.store(budget.exhausted, Ordering::Release);
+            }
+            next += 1;
+            continue;
+        }
+        if workers == 0 {
+            // Every worker is stuck or never started, so nothing else is getting rendered
+            queue.lock().unwrap_or_else(PoisonError::into_inner).clear();
+            let note = match &spawn_error {
+                Some(e) => format!("Couldn't render value: {e}"),
+                None => "render timed out".into(),
+            };
+            rendered[next] = Some(Some(vec![OutputItem::Note(note)]));
+            continue;
+        }
+        let event = match started.values().min() {
+            Some(&start) => {
+                let deadline = start + cfg.render_timeout;
+                events.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => events
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match event {
+            Ok(RenderEvent::Started(pos, start)) => {
+                started.insert(pos, start);
+            }
+            // What a stuck worker finishes after its value was given up on is thrown away
+            Ok(RenderEvent::Done(pos, items)) => {
+                if started.remove(&pos).is_some() {
+                    rendered[pos] = Some(items);
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let now = Instant::now();
+                started.retain(|&pos, &mut start| {
+                    let keep = now < start + cfg.render_timeout;
+                    if !keep {
+                        trace!(position = pos, "Rendering a value timed out");
+                        rendered[pos] =
+                            Some(Some(vec![OutputItem::Note("render timed out".into())]));
+                        workers -= 1;
+                    }
+                    keep
+                });
+            }
+            // Only happens if every worker panicked
+            Err(mpsc::RecvTimeoutError::Disconnected) => workers = 0,
+        }
+    }
+    queue.lock().unwrap_or_else(PoisonError::into_inner).clear();
+}
+
+/// Starts a render worker that takes values off the queue until it's empty or nobody's listening
+/// anymore. Workers keep their [`RenderSlot`] until they finish, so values that time out over and
+/// over can't pile up threads.
+fn spawn_render_worker(
+    queue: Arc<RenderQueue>,
+    events: mpsc::Sender<RenderEvent>,
+    skip_media: Arc<AtomicBool>,
+    cfg: &RunConfig,
+) -> Result<(), String> {
+    let slot = RenderSlot::take().ok_or("too many values are still being rendered")?;
+    let cfg = cfg.clone();
+    std::thread::Builder::new()
+        .name("render".into())
+        .spawn(move || {
+            let _slot = slot;
+            loop {
+                let next = queue
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .pop_front();
+                let Some((pos, value, index)) = next else {
+                    break;
+                };
+                if events
+                    .send(RenderEvent::Started(pos, Instant::now()))
+                    .is_err()
+                {
+                    break;
+                }
+                let skip = skip_media.load(Ordering::Acquire)
+                    && classify_value(&value, &cfg) != OutputKind::Misc;
+                let items = (!skip).then(|| value_to_outputs(value, index, &cfg));
+                if events.send(RenderEvent::Done(pos, items)).is_err() {
+                    break;
+                }
+            }
+        })
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
+
+/// Whether the value is text made only of whitespace, which would show up as a blank line
+fn is_blank_value(value: &uiua::Value) -> bool {
+    match value {
+        uiua::Value::Char(arr) => arr.elements().all(|c| c.is_whitespace()),
+        _ => false,
+    }
+}
+
+/// Like [`run_uiua`], but refuses to run code that uses any of the `denied` primitives
+pub fn run_uiua_denying(code: &str, denied: &[Primitive]) -> Result<Vec<OutputItem>, String> {
+    let code = strip_code_fences(code);
+    if let Some(prim) = primitives_used(code)?
+        .into_iter()
+        .find(|prim| denied.contains(prim))
+    {
+        trace!(code, ?prim, "Code used a denied primitive");
+        return Err(format!("{prim} `{}` isn't allowed here", prim.name()));
+    }
+    run_uiua(code)
+}
+
+/// Compiles the code without running it, in the same experimental mode [`run_uiua`] uses
+fn compile_uiua(code: &str) -> Result<Compiler, String> {
+    let mut compiler = Compiler::with_backend(SafeSys::default());
+    // Only compile, without evaluating anything ahead of time the way the normal mode does
+    compiler.pre_eval_mode(PreEvalMode::Lazy);
+    // Loaded separately, so errors point at lines of the user's code
+    for input in [EXPERIMENTAL_PRAGMA, code] {
+        compiler
+            .load_str(input)
+            .map_err(|e| format!("Error while compiling: {e} "))?;
+    }
+    Ok(compiler)
+}
+
+/// Lists the instructions the code compiles to, in uiua's `.uasm` notation, without running it
+pub fn disassemble(code: &str) -> Result<String, String> {
+    let uasm = compile_uiua(strip_code_fences(code))?.assembly().to_uasm();
+    // The rest lists spans and whole source files, which is just noise when reading the listing
+    let instrs = uasm
+        .split_once("\nTOP SLICES\n")
+        .map_or(&*uasm, |(instrs, _)| instrs);
+    Ok(instrs.trim_end().to_string())
+}
+
+/// The deprecated primitives the code uses, in the order they first appear. Errors if the code
+/// doesn't compile.
+pub fn deprecated_primitives_used(code: &str) -> Result<Vec<Primitive>, String> {
+    let code = strip_code_fences(code);
+    compile_uiua(code)?;
+    Ok(deprecated_primitives_in(code))
+}
+
+fn deprecated_primitives_in(code: &str) -> Vec<Primitive> {
+    let mut deprecated = Vec::new();
+    for span in uiua::lsp::spans(code).0 {
+        if let SpanKind::Primitive(prim, _) = span.value {
+            if prim.is_deprecated() && !deprecated.contains(&prim) {
+                deprecated.push(prim);
+            }
+        }
+    }
+    deprecated
+}
+
+/// The primitive's glyph in backticks followed by its name, or just its name in backticks
+fn describe_primitive(prim: Primitive) -> String {
+    match prim.glyph() {
+        Some(glyph) => format!("`{glyph}` {}", prim.name()),
+        None => format!("`{}`", prim.name()),
+    }
+}
+
+/// Describes a binding the code defines by its signature and doc comment, without running the code
+pub fn describe_binding(code: &str, name: &str) -> Result<Option<String>, String> {
+    let code = strip_code_fences(code);
+    let compiler = compile_uiua(code)?;
+    let Some(binding) = compiler.assembly().bindings.iter().find(|binding| {
+        let (start, end) = (binding.span.start.byte_pos, binding.span.end.byte_pos);
+        code.get(start as usize..end as usize) == Some(name)
+    }) else {
+        return Ok(None);
+    };
+
+    let mut description = match binding.kind.signature() {
+        Some(sig) => format!("{name} {sig}"),
+        None => name.to_string(),
+    };
+    if let Some(comment) = &binding.comment {
+        description.push('\n');
+        description.push_str(comment.text.trim());
+    }
+    Ok(Some(description))
+}
+
+/// Primitives that run their function many times, so they multiply the cost of what's inside
+const LOOPING_PRIMITIVES: &[Primitive] = &[
+    Primitive::Repeat,
+    Primitive::Do,
+    Primitive::Fold,
+    Primitive::Reduce,
+    Primitive::Scan,
+    Primitive::Rows,
+    Primitive::Table,
+    Primitive::Group,
+    Primitive::Partition,
+];
+
+/// A rough guess at how expensive code is to run, see [`estimate_cost`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostEstimate {
+    /// Higher is more expensive. Only meaningful compared to other estimates.
+    pub score: f64,
+    pub primitives: usize,
+    pub loops: usize,
+    /// The biggest number written in the code, which tends to end up as a loop count or length
+    pub largest_literal: f64,
+}
+
+/// Guesses how expensive code is from what it contains, compiling it but never running it.
+///
+/// This is a heuristic and easy to fool: it counts primitives, weighs loops more heavily, and
+/// scales the total by the biggest number literal when it appears next to a range or a loop. It
+/// is good enough to tell `+1 2` from `⇡1e9`, not to predict actual running times.
+pub fn estimate_cost(code: &str) -> Result<CostEstimate, String> {
+    let code = strip_code_fences(code);
+    compile_uiua(code)?;
+
+    let mut primitives = 0;
+    let mut loops = 0;
+    let mut sized = false;
+    let mut largest_literal: f64 = 0.0;
+    for span in uiua::lsp::spans(code).0 {
+        match span.value {
+            SpanKind::Primitive(prim, _) => {
+                primitives += 1;
+                if LOOPING_PRIMITIVES.contains(&prim) {
+                    loops += 1;
+                    sized = true;
+                }
+                sized |= prim == Primitive::Range;
+                if prim == Primitive::Infinity {
+                    largest_literal = f64::INFINITY;
+                }
+            }
+            SpanKind::Number => {
+                let text =
+                    &code[span.span.start.byte_pos as usize..span.span.end.byte_pos as usize];
+                let value = match text.replace('¯', "-").as_str() {
+                    "∞" | "-∞" => f64::INFINITY,
+                    text => text.parse::<f64>().unwrap_or(0.0),
+                };
+                largest_literal = largest_literal.max(value.abs());
+            }
+            _ => {}
+        }
+    }
+
+    let base = (primitives + 10 * loops) as f64;
+    let scale = if sized { largest_literal.max(1.0) } else { 1.0 };
+    Ok(CostEstimate {
+        score: base * scale,
+        primitives,
+        loops,
+        largest_literal,
+    })
+}
+
+/// Returns every primitive the code mentions, in order of first appearance, without running it
+pub fn primitives_used(code: &str) -> Result<Vec<Primitive>, String> {
+    compile_uiua(code)?;
+    Ok(glyphs_in_code(code))
+}
+
+/// Lists the experimental primitives and syntax the code relies on, which is what stops it from
+/// running on the stable pad. Primitives are given by name. Syntax is described the way the
+/// compiler words it, like "Subscripts", and only the first use of it is found.
+pub fn experimental_features_used(code: &str) -> Result<Vec<String>, String> {
+    let code = strip_code_fences(code);
+    let mut features: Vec<String> = (primitives_used(code)?.into_iter())
+        .filter(Primitive::is_experimental)
+        .map(|prim| prim.name().to_string())
+        .collect();
+
+    // Compiling without the pragma makes the compiler point out what needed it
+    let mut compiler = Compiler::with_backend(SafeSys::default());
+    compiler.pre_eval_mode(PreEvalMode::Lazy);
+    if let Err(e) = compiler.load_str(code) {
+        for line in e.to_string().lines() {
+            let Some(end) = line.find(" experimental.") else {
+                continue;
+            };
+            let subject = line[..end].trim_end_matches(" is").trim_end_matches(" are");
+            let subject = subject.split_once(": ").map_or(subject, |(_, s)| s);
+            let is_primitive = Primitive::all().any(|prim| prim.format().to_string() == subject);
+            if !is_primitive && !features.iter().any(|f| f == subject) {
+                features.push(subject.to_string());
+            }
+        }
+    }
+    Ok(features)
+}
+
+/// Returns the distinct primitives the code mentions, in order of first appearance, going only by
+/// its tokens so it works even on code that doesn't compile
+pub fn glyphs_in_code(code: &str) -> Vec<Primitive> {
+    let mut prims = Vec::new();
+    for span in uiua::lsp::spans(code).0 {
+        if let SpanKind::Primitive(prim, _) = span.value {
+            if !prims.contains(&prim) {
+                prims.push(prim);
+            }
+        }
+    }
+    prims
+}
+
+/// Returns the code inside a Discord code block or inline code span, dropping the block's language
+/// tag. Anything not wrapped in backticks is returned trimmed but otherwise as is.
+pub fn strip_code_fences(input: &str) -> &str {
+    let s = input.trim();
+    if let Some(inner) = s.strip_prefix("```").and_then(|s| s.strip_suffix("```")) {
+        // Like Discord, only treat the first word as a language tag if a newline follows it
+        match inner.split_once('\n') {
+            Some((tag, body)) if tag.chars().all(|c| c.is_ascii_alphanumeric()) => body.trim(),
+            _ => inner.trim(),
+        }
+    } else if let Some(inner) = s
+        .strip_prefix('`')
+        .and_then(|s| s.strip_suffix('`'))
+        .filter(|inner| !inner.starts_with('`') && !inner.ends_with('`'))
+    {
+        inner.trim()
+    } else {
+        s
+    }
+}
+
+/// Finds the primitive a docs query refers to. A single character is looked up as a glyph first,
+/// so `=` is always equals. Anything longer is only looked up as a name the formatter accepts, a
+/// full name, an alias, or an ASCII spelling like `<=`, and never by its first character. Names
+/// are matched ignoring case, so `Reduce` and `REDUCE` are both reduce.
+fn resolve_primitive(f: &str) -> Option<Primitive> {
+    let mut chars = f.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if let Some(prim) = Primitive::from_glyph(c) {
+            return Some(prim);
+        }
+    }
+    let lower = f.to_lowercase();
+    Primitive::from_format_name(&lower)
+        .or_else(|| Primitive::from_name(&lower))
+        .or_else(|| Primitive::all().find(|prim| prim.aliases().contains(&lower.as_str())))
+        .or_else(|| {
+            Primitive::all().find(|prim| prim.ascii().is_some_and(|ascii| ascii.to_string() == f))
+        })
+}
+
+/// The name uiua gives the primitive `f` refers to, however it was spelled or cased
+pub fn canonical_primitive_name(f: &str) -> Option<&'static str> {
+    resolve_primitive(f).map(|prim| prim.name())
+}
+
+/// Gets the name of the primitive written with the glyph `c`
+pub fn name_from_glyph(c: char) -> Option<&'static str> {
+    Primitive::from_glyph(c).map(|prim| prim.name())
+}
+
+/// Finds the primitive `f` refers to along with its documentation
+pub fn lookup_docs(f: &str) -> Option<(Primitive, &'static PrimDoc)> {
+    resolve_primitive(f).map(|prim| (prim, prim.doc()))
+}
+
+pub fn get_docs(f: &str) -> String {
+    match lookup_docs(f) {
+        Some((prim, docs)) => {
+            let short = docs
+                .short
+                .iter()
+                .map(print_doc_frag)
+                .map(|s| format!("## {s}"))
+                .collect::<Vec<String>>()
+                .join("\n");
+
+            let long = docs
+                .lines
+                .iter()
+                .take(5)
+                .map(print_docs)
+                .collect::<Vec<String>>()
+                .join("\n");
+            format!(
+                "\n{short}\n\n\n{long}\n\n([More information](https://uiua.org/docs/{}))",
+                prim.name()
+            )
+        }
+        None => format!("No docs found for '{f}', did you spell it right?"),
+    }
+}
+
+/// Same content as [`get_docs`], but as plain text without markdown, emoji or colors
+pub fn get_docs_plain(f: &str) -> String {
+    match lookup_docs(f) {
+        Some((prim, docs)) => {
+            let short = docs.short.iter().map(plain_doc_frag).collect::<String>();
+            let long = docs
+                .lines
+                .iter()
+                .take(5)
+                .map(plain_docs)
+                .collect::<Vec<String>>()
+                .join("\n");
+            format!(
+                "{short}\n\n{long}\n\nMore information: https://uiua.org/docs/{}",
+                prim.name()
+            )
+        }
+        None => format!("No docs found for '{f}', did you spell it right?"),
+    }
+}
+
+/// The primitive's one-line description as plain text, for autocomplete choices
+pub fn primitive_short(f: &str) -> Option<String> {
+    resolve_primitive(f).map(short_description)
+}
+
+fn short_description(prim: Primitive) -> String {
+    let short: String = prim.doc().short.iter().map(plain_doc_frag).collect();
+    short.lines().next().unwrap_or_default().trim().to_string()
+}
+
+/// What a listing of primitives shows for each of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrimitiveInfo {
+    pub primitive: Primitive,
+    pub name: &'static str,
+    pub glyph: Option<char>,
+    /// Same as [`primitive_short`]
+    pub short: String,
+}
+
+impl PrimitiveInfo {
+    pub fn new(primitive: Primitive) -> Self {
+        PrimitiveInfo {
+            primitive,
+            name: primitive.name(),
+            glyph: primitive.glyph(),
+            short: short_description(primitive),
+        }
+    }
+}
+
+/// Every primitive that needs `# Experimental!`, which the bot always turns on
+pub fn experimental_primitives() -> Vec<PrimitiveInfo> {
+    (Primitive::all())
+        .filter(Primitive::is_experimental)
+        .map(PrimitiveInfo::new)
+        .collect()
+}
+
+/// Returns the ASCII spellings the formatter turns into the primitive's glyph
+pub fn glyph_input_hint(f: &str) -> Option<String> {
+    let prim = resolve_primitive(f)?;
+    let name = prim.name();
+    let Some(glyph) = prim.glyph() else {
+        return Some(format!("`{name}` has no glyph, type it as is"));
+    };
+
+    // Multi-word names can't be typed as a single identifier, so only their aliases are useful
+    let prefixes: Vec<&str> = (2..=name.len())
+        .filter_map(|n| name.get(..n))
+        .filter(|prefix| !prefix.contains(' '))
+        .filter(|prefix| Primitive::from_format_name(prefix) == Some(prim))
+        .collect();
+    let mut spellings: Vec<String> = (prefixes.last().into_iter())
+        .chain(prefixes.first())
+        .map(|s| s.to_string())
+        .collect();
+    spellings.extend(prim.aliases().iter().map(|alias| alias.to_string()));
+    spellings.extend(prim.ascii().map(|ascii| ascii.to_string()));
+    spellings.dedup();
+
+    let spellings = spellings
+        .iter()
+        .map(|s| format!("`{s}`"))
+        .collect::<Vec<String>>()
+        .join(" or ");
+    Some(format!("Type {spellings} then format to get `{glyph}`"))
+}
+
+/// Primitive names and aliases starting with `prefix`, ignoring case, sorted and capped at
+/// [`MAX_COMPLETIONS`]
+pub fn complete_primitive(prefix: &str) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    let mut names: Vec<String> = Primitive::all()
+        .flat_map(|prim| std::iter::once(prim.name()).chain(prim.aliases().iter().copied()))
+        .filter(|name| name.to_lowercase().starts_with(&prefix))
+        .map(str::to_string)
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names.truncate(MAX_COMPLETIONS);
+    names
+}
+
+fn print_doc_frag(frag: &PrimDocFragment) -> String {
+    match frag {
+        PrimDocFragment::Text(t) => t.clone(),
+        PrimDocFragment::Code(t) => format!("`{t}`"),
+        PrimDocFragment::Emphasis(t) => format!("_{t}_"),
+        PrimDocFragment::Strong(t) => format!("**{t}**"),
+        PrimDocFragment::Primitive { prim, named } => {
+            if *named {
+                format!("{} `{}`", print_emoji(prim), prim.name())
+            } else {
+                print_emoji(prim)
+            }
+        }
+        PrimDocFragment::Link { text, url } => format!("[{text}]({url})"),
+    }
+}
+
+fn print_docs(line: &PrimDocLine) -> String {
+    match line {
+        PrimDocLine::Text(vs) => vs
+            .iter()
+            .map(print_doc_frag)
+            .collect::<Vec<String>>()
+            .join(" "),
+        PrimDocLine::Example(e) => {
+            let out = match e.output().as_ref().map(|vs| vs.join(";")) {
+                Ok(l) => l,
+                Err(l) => l.to_string(),
+            };
+            let text = format!(
+                "{}\n{}",
+                e.input(),
+                out.lines().map(|l| format!("# {l}\n")).collect::<String>()
+            );
+
+            highlight_code(&text)
+        }
+    }
+}
+
+/// Plain text docs for a primitive where each example is also run, with what the bot renders shown
+/// under what the docs say it gives so the two can be compared. At most [`MAX_LIVE_EXAMPLES`]
+/// examples are run.
+pub fn docs_with_live_examples(f: &str) -> Result<String, String> {
+    let (_, docs) = lookup_docs(f)
+        .ok_or_else(|| format!("No docs found for '{f}', did you spell it right?"))?;
+    let cfg = RunConfig::default();
+    let comment = |prefix: &str, text: &str| -> String {
+        text.lines().map(|l| format!("\n# {prefix}: {l}")).collect()
+    };
+
+    let examples: Vec<String> = (docs.lines.iter())
+        .filter_map(|line| match line {
+            PrimDocLine::Example(e) => Some(e),
+            PrimDocLine::Text(_) => None,
+        })
+        .take(MAX_LIVE_EXAMPLES)
+        .map(|e| {
+            let documented = match e.output() {
+                Ok(values) => values.join("\n"),
+                Err(err) => err.to_string(),
+            };
+            let live = match run_uiua(e.input()) {
+                Ok(items) => (items.iter())
+                    .map(|item| match item {
+                        OutputItem::Audio(_) => "<audio>\n".to_string(),
+                        OutputItem::Image(_) => "<image>\n".to_string(),
+                        OutputItem::Gif(_) => "<gif>\n".to_string(),
+                        item => render_item_text(item, &cfg).unwrap_or_default(),
+                    })
+                    .collect(),
+                Err(err) => err,
+            };
+            format!(
+                "{}{}{}",
+                e.input(),
+                comment("docs", &documented),
+                comment("bot", &live)
+            )
+        })
+        .collect();
+
+    let short = docs.short.iter().map(plain_doc_frag).collect::<String>();
+    Ok(format!("{short}\n\n{}", examples.join("\n\n")))
+}
+
+/// The docs from [`get_docs`] together with what running the primitive's first example gives, or
+/// no outputs if it has no examples. An example that fails, as some are meant to, has its error
+/// as the only output.
+pub fn docs_and_demo(f: &str) -> Result<(String, Vec<OutputItem>), String> {
+    let (_, docs) = lookup_docs(f)
+        .ok_or_else(|| format!("No docs found for '{f}', did you spell it right?"))?;
+    let example = docs.lines.iter().find_map(|line| match line {
+        PrimDocLine::Example(e) => Some(e.input()),
+        PrimDocLine::Text(_) => None,
+    });
+    let demo = match example.map(run_uiua) {
+        Some(Ok(items)) => items,
+        Some(Err(err)) => vec![OutputItem::Note(err)],
+        None => Vec::new(),
+    };
+    Ok((get_docs(f), demo))
+}
+
+/// Shows what the primitive does by running it on made up inputs, trying ones that suit its class
+/// and arity until one works. The first output is a note with the code that ran. Audio is turned
+/// off, since the sample arrays would mostly be taken as audio otherwise.
+pub fn demo_primitive(f: &str) -> Result<Vec<OutputItem>, String> {
+    let prim = resolve_primitive(f)
+        .ok_or_else(|| format!("No docs found for '{f}', did you spell it right?"))?;
+    let spelling = prim.glyph().map_or(prim.name().to_string(), String::from);
+    let candidates = demo_inputs(prim).ok_or_else(|| {
+        format!(
+            "{} can't be shown on made up inputs",
+            describe_primitive(prim)
+        )
+    })?;
+    let cfg = RunConfig {
+        audio_output: false,
+        ..RunConfig::default()
+    };
+    let mut last_error = String::new();
+    for inputs in candidates {
+        let code = format!("{spelling}{inputs}");
+        match run_uiua_with_config(&code, &cfg) {
+            Ok(outputs) => {
+                return Ok(std::iter::once(OutputItem::Note(code))
+                    .chain(outputs)
+                    .collect())
+            }
+            Err(err) => last_error = err,
+        }
+    }
+    Err(last_error)
+}
+
+/// What [`demo_primitive`] puts after the primitive, in the order they're tried, or `None` if the
+/// primitive shouldn't be run on its own. Modifiers get a function first, picked to suit them.
+fn demo_inputs(prim: Primitive) -> Option<Vec<String>> {
+    use uiua::PrimClass;
+
+    const MONADIC: &[&str] = &["[1_2_3 4_5_6]", "[3 1 4 1 5]", "\"hello\"", "5", "\"42\""];
+    const DYADIC: &[&str] = &[
+        "1 [1_2_3 4_5_6]",
+        "[1 2] [1_2_3 4_5_6]",
+        "[1 2 3] [2 3 4 5]",
+        "\"ab\" \"abc\"",
+    ];
+    let with = |functions: &[&str], inputs: &[&str]| {
+        (functions.iter())
+            .flat_map(|f| inputs.iter().map(move |inputs| format!("{f} {inputs}")))
+            .collect()
+    };
+    Some(match (prim.class(), prim.modifier_args(), prim.args()) {
+        (PrimClass::Sys(_) | PrimClass::Debug | PrimClass::Thread | PrimClass::Comptime, ..) => {
+            return None
+        }
+        (PrimClass::AggregatingModifier, Some(1), _) => with(
+            &["+"],
+            // The second is for an initial value, the third for indices that group
+            &[
+                "[1 2 3 4]",
+                "[1 2 3 4] 0",
+                "[0 0 1 1] [1 2 3 4]",
+                MONADIC[0],
+            ],
+        ),
+        (_, Some(1), _) => with(&["(×2)", "⇌", "+"], &["[1 2 3 4]", MONADIC[0], "3 4"]),
+        (_, Some(2), _) => with(&["(+1)(×2)", "+×", "⊢⇌"], &["[1 2 3]", "3 4"]),
+        (_, Some(_), _) => return None,
+        (_, None, Some(0)) => vec![String::new()],
+        (PrimClass::MonadicPervasive, ..) => vec![" [¯1 0 2.5 4]".into()],
+        (PrimClass::DyadicPervasive, ..) => vec![" 3 [1 2 3 4]".into()],
+        (_, None, Some(1)) => MONADIC.iter().map(|inputs| format!(" {inputs}")).collect(),
+        (_, None, Some(2)) => DYADIC.iter().map(|inputs| format!(" {inputs}")).collect(),
+        _ => return None,
+    })
+}
+
+/// Finds doc examples, across every primitive, whose code mentions `keyword`. The keyword matches
+/// if it appears in the code as written, or if it names a primitive whose glyph appears in it, so
+/// "transpose" finds examples using `⍉`. Sorted by primitive then code, and capped.
+pub fn examples_mentioning(keyword: &str) -> Vec<(Primitive, String)> {
+    let keyword = keyword.trim().to_lowercase();
+    if keyword.is_empty() {
+        return Vec::new();
+    }
+    let glyph = resolve_primitive(&keyword).and_then(|prim| prim.glyph());
+    let mut examples: Vec<(Primitive, String)> = Primitive::all()
+        .flat_map(|prim| {
+            prim.doc().lines.iter().filter_map(move |line| match line {
+                PrimDocLine::Example(e) => Some((prim, e.input().to_string())),
+                PrimDocLine::Text(_) => None,
+            })
+        })
+        .filter(|(_, code)| {
+            code.to_lowercase().contains(&keyword) || glyph.is_some_and(|g| code.contains(g))
+        })
+        .collect();
+    examples.sort_by(|(a, a_code), (b, b_code)| a.name().cmp(b.name()).then(a_code.cmp(b_code)));
+    examples.dedup();
+    examples.truncate(MAX_EXAMPLES);
+    examples
+}
+
+fn plain_doc_frag(frag: &PrimDocFragment) -> String {
+    match frag {
+        PrimDocFragment::Text(t) | PrimDocFragment::Emphasis(t) | PrimDocFragment::Strong(t) => {
+            t.clone()
+        }
+        PrimDocFragment::Code(t) => format!("`{t}`"),
+        PrimDocFragment::Primitive { prim, named } => match prim.glyph() {
+            Some(glyph) if *named => format!("{glyph} {}", prim.name()),
+            Some(glyph) => glyph.to_string(),
+            None => prim.name().to_string(),
+        },
+        PrimDocFragment::Link { text, url } => format!("{text} ({url})"),
+    }
+}
+
+fn plain_docs(line: &PrimDocLine) -> String {
+    match line {
+        PrimDocLine::Text(vs) => vs.iter().map(plain_doc_frag).collect(),
+        PrimDocLine::Example(e) => {
+            let out = match e.output().as_ref().map(|vs| vs.join(";")) {
+                Ok(l) => l,
+                Err(l) => l.to_string(),
+            };
+            let outputs: String = out.lines().map(|l| format!("\n# {l}")).collect();
+            format!("{}{outputs}", e.input())
+        }
+    }
+}
+
+fn print_emoji(c: &Primitive) -> String {
+    if c.is_experimental() {
+        c.names()
+            .glyph
+            .map(|g| g.to_string())
+            .unwrap_or(c.name().to_string())
+    } else {
+        let spaceless_name = normalize_name(c.name());
+        if let Some(_id) = EMOJI_MAP.get(&*spaceless_name) {
+            //format!("<:{}:{}>", spaceless_name, id) // For when the bot has it internally
+            format!(":{}:", spaceless_name)
+        } else {
+            format!("<{}>", spaceless_name)
+        }
+    }
+}
+
+/// Turns a primitive name into the form the glyphlist keys its emoji by, e.g. "un box" into "unbox"
+pub fn normalize_name(name: &str) -> String {
+    name.split(' ').collect()
+}
+
+/// Finds the primitive whose name normalizes to the same thing as `name`
+fn primitive_from_normalized(name: &str) -> Option<Primitive> {
+    let normalized = normalize_name(name);
+    Primitive::all().find(|p| normalize_name(p.name()) == normalized)
+}
+
+/// Check whether the linked uiua version has a primitive with this name, ignoring spaces the way
+/// the glyphlist does
+pub fn primitive_exists(name: &str) -> bool {
+    Primitive::from_name(name).is_some() || primitive_from_normalized(name).is_some()
+}
+
+/// Names in the glyphlist that don't belong to any primitive in the linked uiua version, sorted
+pub fn stale_emoji_names() -> Vec<&'static str> {
+    let mut stale: Vec<&str> = EMOJI_MAP
+        .keys()
+        .copied()
+        .filter(|name| !primitive_exists(name))
+        .collect();
+    stale.sort_unstable();
+    stale
+}
+
+/// Names in the glyphlist whose emoji id isn't among the `available` ones, such as the custom
+/// emoji a server has, sorted
+pub fn audit_emoji_ids(available: &HashSet<&str>) -> Vec<String> {
+    let mut missing: Vec<String> = (EMOJI_MAP.iter())
+        .filter(|(_, id)| !available.contains(*id))
+        .map(|(name, _)| name.to_string())
+        .collect();
+    missing.sort_unstable();
+    missing
+}
+
+/// The ways uiua.org can open a piece of code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PadLinkKind {
+    /// The full pad editor
+    #[default]
+    Pad,
+    /// Just the output of running the code
+    Embed,
+    /// A small editor that runs the code immediately
+    EmbedPad,
+}
+
+impl PadLinkKind {
+    fn path(self) -> &'static str {
+        match self {
+            PadLinkKind::Pad => "pad",
+            PadLinkKind::Embed => "embed",
+            PadLinkKind::EmbedPad => "embedpad",
+        }
+    }
+}
+
+/// The version of uiua this crate runs, see [`UIUA_VERSION`]
+pub fn uiua_version() -> &'static str {
+    UIUA_VERSION
+}
+
+/// Get a uiua.org link of the given kind that opens `code`
+pub fn pad_link(code: &str, kind: PadLinkKind) -> String {
+    let encoded = URL_SAFE.encode(code);
+    format!(
+        "https://www.uiua.org/{}?src={}__{encoded}",
+        kind.path(),
+        uiua_version()
+    )
+}
+
+/// Gets the code back out of a uiua.org link, the reverse of [`pad_link`]. Links with and without
+/// base64 padding are both accepted, since the pad drops it.
+pub fn code_from_pad_link(url: &str) -> Result<String, String> {
+    use base64::engine::{general_purpose::GeneralPurpose, DecodePaddingMode};
+    const LENIENT_URL_SAFE: GeneralPurpose = GeneralPurpose::new(
+        &base64::alphabet::URL_SAFE,
+        base64::engine::general_purpose::PAD
+            .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    );
+
+    let url = url.trim().trim_start_matches('<').trim_end_matches('>');
+    let rest = (url.strip_prefix("https://"))
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(url);
+    let rest = rest.strip_prefix("www.").unwrap_or(rest);
+    let (path, query) = (rest.strip_prefix("uiua.org/"))
+        .and_then(|rest| rest.split_once('?'))
+        .ok_or_else(|| format!("{url} isn't a uiua.org link"))?;
+    if ![PadLinkKind::Pad, PadLinkKind::Embed, PadLinkKind::EmbedPad]
+        .iter()
+        .any(|kind| kind.path() == path)
+    {
+        return Err(format!("uiua.org/{path} doesn't open code"));
+    }
+    let src = (query.split('&'))
+        .find_map(|param| param.strip_prefix("src="))
+        .ok_or("The link has no src= with the code in it")?;
+    let (_version, encoded) = src
+        .split_once("__")
+        .ok_or("The link's code is missing its version prefix")?;
+    let encoded = encoded.replace("%3D", "=");
+    let bytes = LENIENT_URL_SAFE
+        .decode(&encoded)
+        .map_err(|e| format!("The link's code isn't valid base64: {e}"))?;
+    String::from_utf8(bytes).map_err(|_| "The link's code isn't valid text".into())
+}
+
+/// Runs the code a uiua.org link opens. Links that can't be read are rejected before anything runs.
+pub fn run_pad_link(url: &str, cfg: &RunConfig) -> Result<Vec<OutputItem>, String> {
+    let code = code_from_pad_link(url).map_err(|e| format!("Couldn't read the pad link: {e}"))?;
+    run_uiua_with_config(&code, cfg)
+}
+
+pub fn format_and_get_pad_link(code: &str) -> Result<String, String> {
+    let config = FormatConfig::default();
+    let formatted = format_str(code, &config)
+        .map_err(|e| format!("Error while formatting: {e} "))?
+        .output;
+
+    let link = pad_link(code, PadLinkKind::default());
+
+    Ok(format!("[pad]({link}) for: {}", highlight_code(&formatted)))
+}
+
+/// How much the formatter is allowed to change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FormatStyle {
+    /// Whatever the uiua formatter does, the same as the pad
+    #[default]
+    Canonical,
+    /// Only turn names and ASCII spellings into glyphs, keeping the user's spacing and line breaks
+    Minimal,
+}
+
+/// Formats the code in the given style. Code the formatter rejects is an error in either style.
+pub fn format_code(code: &str, style: FormatStyle) -> Result<String, String> {
+    let formatted = format_str(code, &FormatConfig::default())
+        .map_err(|e| format!("Error while formatting: {e} "))?;
+    if style == FormatStyle::Canonical {
+        return Ok(formatted.output);
+    }
+
+    // The formatter has no option to leave layout alone, so substitute the glyphs by hand
+    let mut minimal = String::with_capacity(code.len());
+    let mut cursor = 0;
+    for span in uiua::lsp::spans(code).0 {
+        let SpanKind::Primitive(prim, _) = span.value else {
+            continue;
+        };
+        let (start, end) = (
+            span.span.start.byte_pos as usize,
+            span.span.end.byte_pos as usize,
+        );
+        if let Some(glyph) = prim.glyph() {
+            minimal.push_str(&code[cursor..start]);
+            minimal.push(glyph);
+            cursor = end;
+        }
+    }
+    minimal.push_str(&code[cursor..]);
+    Ok(minimal)
+}
+
+/// Shows what the formatter changed as a Discord diff block, with removed lines marked `-` and
+/// added ones `+`, or says there was nothing to change
+pub fn format_inline_diff(code: &str) -> Result<String, String> {
+    let code = strip_code_fences(code);
+    let formatted = format_code(code, FormatStyle::Canonical)?;
+    let before: Vec<&str> = code.lines().map(str::trim_end).collect();
+    let after: Vec<&str> = formatted.lines().map(str::trim_end).collect();
+    if before == after {
+        return Ok("No changes, the code is already formatted".into());
+    }
+
+    // Longest common subsequence of lines, filled in from the end so it can be walked forwards
+    let mut common = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i][j] = if before[i] == after[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut diff = String::from("```diff\n");
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i] == after[j] {
+            diff.push_str(&format!("  {}\n", before[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if j == after.len() || (i < before.len() && common[i + 1][j] >= common[i][j + 1]) {
+            diff.push_str(&format!("- {}\n", before[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+ {}\n", after[j]));
+            j += 1;
+        }
+    }
+    diff.push_str("```");
+    Ok(diff)
+}
+
+/// Format `code` and move the byte offset `cursor` to the matching spot in the formatted code
+///
+/// When a name is replaced by its glyph the cursor lands after the glyph, so typing can continue
+pub fn format_with_cursor(code: &str, cursor: usize) -> Result<(String, usize), String> {
+    if !code.is_char_boundary(cursor) {
+        return Err(format!(
+            "Cursor {cursor} is not a valid position in the code"
+        ));
+    }
+    let formatted = format_str(code, &FormatConfig::default())
+        .map_err(|e| format!("Error while formatting: {e} "))?;
+
+    let char_pos = code[..cursor].chars().count() as u32;
+    let (_, new_pos) = formatted.map_char_pos(char_pos);
+    let new_cursor = formatted
+        .output
+        .char_indices()
+        .nth(new_pos as usize)
+        .map_or(formatted.output.len(), |(i, _)| i);
+
+    trace!(cursor, new_cursor, "Remapped cursor after formatting");
+    Ok((formatted.output, new_cursor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Pseudorandom pixels, which PNG can't compress much
+    fn noise_image(width: u32, height: u32) -> image::RgbImage {
+        let mut state: u32 = 0x2545_f491;
+        image::RgbImage::from_fn(width, height, |_, _| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let [r, g, b, _] = state.to_le_bytes();
+            image::Rgb([r, g, b])
+        })
+    }
+
+    #[test]
+    fn strips_fenced_blocks() {
+        assert_eq!(strip_code_fences("```\n+1 2\n```"), "+1 2");
+        assert_eq!(strip_code_fences("```uiua\n+1 2\n```"), "+1 2");
+        assert_eq!(strip_code_fences("  ```ua\n⇡5\n/+⇡5\n```  "), "⇡5\n/+⇡5");
+        assert_eq!(strip_code_fences("```+1 2```"), "+1 2");
+    }
+
+    #[test]
+    fn strips_inline_code() {
+        assert_eq!(strip_code_fences("`+1 2`"), "+1 2");
+        assert_eq!(strip_code_fences("`"), "`");
+    }
+
+    #[test]
+    fn leaves_bare_code_alone() {
+        assert_eq!(strip_code_fences(" +1 2 "), "+1 2");
+        assert_eq!(strip_code_fences("$ raw `ticks`"), "$ raw `ticks`");
+    }
+
+    #[test]
+    fn runs_fenced_code() {
+        match &run_uiua("```uiua\n+1 2\n```").unwrap()[..] {
+            [OutputItem::Misc(value)] => assert_eq!(value.show(), "3"),
+            _ => panic!("Expected a single value"),
+        }
+    }
+
+    #[test]
+    fn lists_used_primitives() {
+        assert_eq!(
+            primitives_used("/+ ⇌ reverse [1 2 3]"),
+            Ok(vec![Primitive::Reduce, Primitive::Add, Primitive::Reverse])
+        );
+        assert!(primitives_used("[1 2").is_err());
+    }
+
+    #[test]
+    fn denies_primitives() {
+        let denied = [Primitive::Reverse];
+        let err = run_uiua_denying("/+ ⇌[1 2 3]", &denied).err().unwrap();
+        assert!(err.contains("reverse"));
+        assert!(run_uiua_denying("/+ [1 2 3]", &denied).is_ok());
+    }
+
+    #[test]
+    fn input_hint_for_glyph() {
+        let hint = glyph_input_hint("/").unwrap();
+        assert!(hint.contains("`reduce`"));
+        assert!(hint.contains("`/`"));
+        assert_eq!(glyph_input_hint("reduce"), Some(hint));
+    }
+
+    #[test]
+    fn input_hint_for_ascii() {
+        assert!(glyph_input_hint("≠").unwrap().contains("`!=`"));
+        assert_eq!(glyph_input_hint("notaprimitive"), None);
+    }
+
+    #[test]
+    fn timeout_keeps_partial_result() {
+        let cfg = RunConfig {
+            execution_limit: Duration::from_millis(100),
+            ..Default::default()
+        };
+        // What was shown before the loop is still there when it times out
+        match &run_uiua_with_config("&ims ÷99⊞+.⇡50\n⍥(+1)∞ 0", &cfg).unwrap()[..] {
+            [OutputItem::Image(_), .., OutputItem::Note(note)] => {
+                assert_eq!(note, "Timed out, showing partial result");
+            }
+            outputs => panic!("Expected the image shown before the loop, got {outputs:?}"),
+        }
+    }
+
+    #[test]
+    fn timeout_without_values_errors() {
+        let cfg = RunConfig {
+            execution_limit: Duration::from_millis(100),
+            ..Default::default()
+        };
+        assert!(run_uiua_with_config("⍥(;+1 1)∞", &cfg).is_err());
+    }
+
+    #[test]
+    fn splits_statements() {
+        let code = "F ← (\n  +1\n)\n# Doubles\nG ← ×2\nF G 1\n\n2";
+        assert_eq!(
+            split_statements(code),
+            vec![
+                (0, "F ← (\n  +1\n)\n"),
+                (3, "# Doubles\nG ← ×2\n"),
+                (5, "F G 1\n\n"),
+                (7, "2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn statements_share_bindings_and_stack() {
+        match &run_uiua("X ← 5\n1 2\n+X").unwrap()[..] {
+            [OutputItem::Misc(a), OutputItem::Misc(b)] => {
+                assert_eq!((a.show(), b.show()), ("2".into(), "6".into()))
+            }
+            _ => panic!("Expected two values"),
+        }
+    }
+
+    #[test]
+    fn error_lines_match_whole_program() {
+        let err = run_uiua("1\n2\n+ @a @b").unwrap_err();
+        let mut runtime = Uiua::with_safe_sys();
+        let whole = runtime.run_str("1\n2\n+ @a @b").err().unwrap();
+        assert_eq!(err, format!("Error while running: {whole} "));
+    }
+
+    #[test]
+    fn error_lines_are_the_users() {
+        let err = run_uiua("1\n\n2\n+ @a @b").unwrap_err();
+        assert!(err.contains("4:1"), "{err}");
+        let cfg = RunConfig {
+            prelude: Some("A ← 1\nB ← 2".into()),
+            ..RunConfig::default()
+        };
+        let err = run_uiua_with_config("A\n+ @a @b", &cfg).unwrap_err();
+        assert!(err.contains("2:1"), "{err}");
+        let err = primitives_used("1\n(").unwrap_err();
+        assert!(err.contains("2:1"), "{err}");
+    }
+
+    #[test]
+    fn small_images_not_downscaled() {
+        let image = noise_image(64, 64).into();
+        let (_, downscaled) = encode_png_within(image, usize::MAX, PngSpeed::Default).unwrap();
+        assert!(!downscaled);
+    }
+
+    #[test]
+    fn large_images_downscaled() {
+        let value = uiua::encode::rgb_image_to_array(noise_image(400, 300)).into();
+        let cfg = RunConfig {
+            max_image_bytes: 50_000,
+            ..Default::default()
+        };
+        match &value_to_outputs(value, 0, &cfg)[..] {
+            [OutputItem::Image(bytes), OutputItem::Note(note)] => {
+                assert!(bytes.len() <= cfg.max_image_bytes);
+                assert_eq!(note, "(downscaled)");
+            }
+            _ => panic!("Expected a downscaled image"),
+        }
+    }
+
+    #[test]
+    fn cursor_follows_glyphs() {
+        let (formatted, cursor) = format_with_cursor("reverse range 5", 7).unwrap();
+        assert_eq!(formatted.trim_end(), "⇌ ⇡ 5");
+        assert_eq!(&formatted[..cursor], "⇌");
+    }
+
+    #[test]
+    fn cursor_at_end_stays_at_end() {
+        let code = "reverse range 5";
+        let (formatted, cursor) = format_with_cursor(code, code.len()).unwrap();
+        assert_eq!(cursor, formatted.trim_end().len());
+    }
+
+    #[test]
+    fn cursor_inside_char_rejected() {
+        assert!(format_with_cursor("⇡5", 1).is_err());
+    }
+
+    #[test]
+    fn pad_links_decode_to_source() {
+        let code = "⇌⇡5 # reversed";
+        for kind in [PadLinkKind::Pad, PadLinkKind::Embed, PadLinkKind::EmbedPad] {
+            let link = pad_link(code, kind);
+            assert!(link.contains(&format!("/{}?src=", kind.path())));
+            let (_, encoded) = link.split_once("__").unwrap();
+            assert_eq!(URL_SAFE.decode(encoded).unwrap(), code.as_bytes());
+        }
+    }
+
+    #[test]
+    fn pad_link_of_unformattable_code_errors() {
+        assert!(format_and_get_pad_link("\"unclosed")
+            .unwrap_err()
+            .starts_with("Error while formatting"));
+    }
+
+    #[test]
+    fn default_link_is_pad() {
+        assert!(format_and_get_pad_link("1")
+            .unwrap()
+            .contains("uiua.org/pad?src="));
+    }
+
+    #[test]
+    fn classifies_without_encoding() {
+        let cfg = RunConfig::default();
+        let classify = |code: &str| {
+            let mut rt = Uiua::with_safe_sys();
+            rt.run_str(code).unwrap();
+            classify_value(&rt.pop("value").unwrap(), &cfg)
+        };
+        assert_eq!(classify("∿×τ÷44100⇡44100"), OutputKind::Audio);
+        assert_eq!(classify("÷99⊞+.⇡50"), OutputKind::Image);
+        assert_eq!(classify("⊞+.⇡10"), OutputKind::Misc);
+        assert_eq!(classify("\"hello\""), OutputKind::Misc);
+    }
+
+    #[test]
+    fn primitives_exist_with_or_without_spaces() {
+        assert!(primitive_exists("reverse"));
+        assert!(primitive_exists("absolute value"));
+        assert!(primitive_exists("absolutevalue"));
+        assert!(!primitive_exists("frobnicate"));
+    }
+
+    #[test]
+    fn glyphlist_matches_uiua_version() {
+        // `binding` is the emoji for `←`, which is syntax rather than a primitive
+        assert_eq!(stale_emoji_names(), ["binding"]);
+    }
+
+    #[test]
+    fn missing_emoji_ids_reported() {
+        let mut available: HashSet<&str> = EMOJI_MAP.values().copied().collect();
+        available.remove(EMOJI_MAP["with"]);
+        available.remove(EMOJI_MAP["under"]);
+        assert_eq!(audit_emoji_ids(&available), ["under", "with"]);
+        assert!(audit_emoji_ids(&EMOJI_MAP.values().copied().collect()).is_empty());
+        assert_eq!(audit_emoji_ids(&HashSet::new()).len(), EMOJI_MAP.len());
+    }
+
+    #[test]
+    fn definitions_only_lists_bindings() {
+        let outputs = run_uiua("Foo ← +1\nBar ← 5").unwrap();
+        assert!(
+            matches!(&outputs[..], [OutputItem::Note(n)] if n == "defined: Foo (function |1.1), Bar")
+        );
+    }
+
+    #[test]
+    fn functions_show_their_signature() {
+        let outputs = run_uiua("Avg ← ÷⊃⧻/+\nPair ← ⊟").unwrap();
+        assert!(
+            matches!(&outputs[..], [OutputItem::Note(n)] if n == "defined: Avg (function |1.1), Pair (function |2.1)"),
+            "{outputs:?}"
+        );
+    }
+
+    #[test]
+    fn definitions_with_results_show_results() {
+        let outputs = run_uiua("Foo ← +1\nFoo 2").unwrap();
+        assert!(matches!(&outputs[..], [OutputItem::Misc(v)] if v.show() == "3"));
+    }
+
+    #[test]
+    fn collapses_repeated_values() {
+        let cfg = RunConfig {
+            collapse_duplicates: true,
+            ..Default::default()
+        };
+        let outputs = run_uiua_with_config("1 1 1 2 1", &cfg).unwrap();
+        let shown: Vec<String> = outputs
+            .iter()
+            .map(|item| match item {
+                OutputItem::Misc(v) => v.show(),
+                OutputItem::Note(n) => n.clone(),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(shown, ["1", "2", "1", "×3"]);
+    }
+
+    #[test]
+    fn continuation_counts_collapsed_values() {
+        let cfg = RunConfig {
+            collapse_duplicates: true,
+            ..Default::default()
+        };
+        let outputs = run_uiua_with_config("1 1 1 1 1 2 3 4 5 6 7 8 9 10 11 12 13", &cfg).unwrap();
+        assert!(matches!(
+            outputs.last(),
+            Some(OutputItem::Continuation(7, StackEnd::Top))
+        ));
+    }
+
+    #[test]
+    fn duplicates_kept_by_default() {
+        assert_eq!(run_uiua("1 1 1").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn plain_docs_have_no_markup() {
+        let docs = get_docs_plain("reverse");
+        assert!(docs.starts_with("Reverse the rows of an array"), "{docs}");
+        assert!(!docs.contains(":reverse:"));
+        assert!(!docs.contains("##"));
+        assert!(!docs.contains('\u{1b}'));
+        assert!(docs.contains("⇌"));
+    }
+
+    #[test]
+    fn plain_docs_for_unknown_name() {
+        assert!(get_docs_plain("frobnicate").starts_with("No docs found"));
+    }
+
+    #[tokio::test]
+    async fn runs_off_the_async_runtime() {
+        let outputs = run_uiua_async("+1 2".into(), RunConfig::default())
+            .await
+            .unwrap();
+        assert!(matches!(&outputs[..], [OutputItem::Misc(v)] if v.show() == "3"));
+    }
+
+    #[test]
+    fn names_glyphs() {
+        assert_eq!(name_from_glyph('+'), Some("add"));
+        assert_eq!(name_from_glyph('⇌'), Some("reverse"));
+        assert_eq!(name_from_glyph('a'), None);
+    }
+
+    #[test]
+    fn waveform_follows_audio() {
+        let cfg = RunConfig {
+            render_waveform: true,
+            ..Default::default()
+        };
+        let outputs = run_uiua_with_config("⊟.∿×τ×220÷44100⇡44100", &cfg).unwrap();
+        let [OutputItem::Audio(_), OutputItem::Image(png)] = &outputs[..] else {
+            panic!("expected audio then a waveform, got {outputs:?}");
+        };
+        let plot = image::load_from_memory(png).unwrap();
+        assert_eq!(plot.width(), WAVEFORM_WIDTH);
+        assert_eq!(plot.height(), 2 * WAVEFORM_CHANNEL_HEIGHT);
+    }
+
+    #[test]
+    fn thumbnail_follows_audio() {
+        let cfg = RunConfig {
+            audio_thumbnail: true,
+            ..Default::default()
+        };
+        // Gets louder over the clip
+        let code = "T ← ÷44100⇡44100\n⊟.×T∿×τ×220 T";
+        let outputs = run_uiua_with_config(code, &cfg).unwrap();
+        let [OutputItem::Audio(_), OutputItem::Image(png)] = &outputs[..] else {
+            panic!("expected audio then a thumbnail, got {outputs:?}");
+        };
+        let strip = image::load_from_memory(png).unwrap().to_rgb8();
+        assert_eq!(
+            strip.dimensions(),
+            (AUDIO_THUMBNAIL_WIDTH, AUDIO_THUMBNAIL_HEIGHT)
+        );
+        let background = *strip.get_pixel(0, 0);
+        let bar_height = |x| {
+            (strip
+                .rows()
+                .filter(|row| row.clone().nth(x) != Some(&background)))
+            .count()
+        };
+        assert!(bar_height(10) < bar_height(190));
+    }
+
+    #[test]
+    fn no_waveform_by_default() {
+        let outputs = run_uiua("∿×τ×220÷44100⇡44100").unwrap();
+        assert!(matches!(&outputs[..], [OutputItem::Audio(_)]));
+    }
+
+    #[test]
+    fn completes_primitive_names() {
+        let completions = complete_primitive("Re");
+        assert!(completions.contains(&"reverse".to_string()));
+        assert!(completions.contains(&"reduce".to_string()));
+        assert!(completions.is_sorted());
+        assert!(completions.iter().all(|c| c.starts_with("re")));
+    }
+
+    #[test]
+    fn completions_are_capped() {
+        assert_eq!(complete_primitive("").len(), MAX_COMPLETIONS);
+        assert!(complete_primitive("zzz").is_empty());
+    }
+
+    #[test]
+    fn multi_word_names_round_trip() {
+        for prim in [
+            Primitive::Abs,
+            Primitive::Ne,
+            Primitive::Le,
+            Primitive::Ge,
+            Primitive::IndexOf,
+        ] {
+            let key = normalize_name(prim.name());
+            assert!(!key.contains(' '));
+            assert!(
+                EMOJI_MAP.contains_key(&*key),
+                "{key} missing from glyphlist"
+            );
+            assert_eq!(print_emoji(&prim), format!(":{key}:"));
+            assert_eq!(primitive_from_normalized(&key), Some(prim));
+        }
+    }
+
+    #[test]
+    fn labels_stack_positions() {
+        let cfg = RunConfig {
+            label_stack_positions: true,
+            ..Default::default()
+        };
+        let outputs = run_uiua_with_config("1 2 3", &cfg).unwrap();
+        let shown: Vec<String> = outputs
+            .iter()
+            .map(|item| match item {
+                OutputItem::Misc(v) => v.show(),
+                OutputItem::Label(l) => l.clone(),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(shown, ["[2]", "3", "[1]", "2", "[top]", "1"]);
+    }
+
+    #[test]
+    fn expects_matching_results() {
+        assert_eq!(run_and_expect("/+⇡5", "10"), Ok(true));
+        assert_eq!(run_and_expect("⇌⇡3", "[2 1 0]"), Ok(true));
+        assert_eq!(run_and_expect("⇡3", "[2 1 0]"), Ok(false));
+    }
+
+    #[test]
+    fn expect_reports_which_side_failed() {
+        assert!(run_and_expect("+", "1")
+            .unwrap_err()
+            .starts_with("The code failed"));
+        assert!(run_and_expect("1", "+")
+            .unwrap_err()
+            .starts_with("The expected value failed"));
+        assert!(run_and_expect("1", "Foo ← 1")
+            .unwrap_err()
+            .contains("left nothing"));
+    }
+
+    #[test]
+    fn summarizes_outputs() {
+        let items = [
+            OutputItem::Image(vec![0; 12 * 1024].into()),
+            OutputItem::Misc(1.into()),
+            OutputItem::Image(vec![0; 8000].into()),
+        ];
+        assert_eq!(summarize_outputs(&items), "2 images (12KB, 8KB), 1 misc");
+        assert_eq!(
+            items[0].descriptor(),
+            OutputDescriptor {
+                kind: "image",
+                bytes: 12 * 1024
+            }
+        );
+    }
+
+    #[test]
+    fn prelude_is_available() {
+        let cfg = RunConfig {
+            prelude: Some("Double ← ×2\nHalf ← ÷2".into()),
+            ..Default::default()
+        };
+        let outputs = run_uiua_with_config("Double Half 5", &cfg).unwrap();
+        assert!(matches!(&outputs[..], [OutputItem::Misc(v)] if v.show() == "5"));
+    }
+
+    #[test]
+    fn prelude_keeps_user_error_lines() {
+        let cfg = RunConfig {
+            prelude: Some("Double ← ×2\nHalf ← ÷2\nThird ← ÷3".into()),
+            ..Default::default()
+        };
+        let code = "1\n2\n+ @a @b";
+        let with_prelude = run_uiua_with_config(code, &cfg).unwrap_err();
+        assert_eq!(with_prelude, run_uiua(code).unwrap_err());
+    }
+
+    #[test]
+    fn reused_prelude_runs_are_isolated() {
+        let cfg = RunConfig {
+            prelude: Some("Triple ← ×3\nQuarter ← ÷4".into()),
+            reuse_prelude: true,
+            ..Default::default()
+        };
+        let first = run_uiua_with_config("Secret ← 7\n&p \"hi\"\nTriple Secret", &cfg).unwrap();
+        assert!(matches!(&first[..], [.., OutputItem::Misc(v)] if v.show() == "21"));
+        let second = run_uiua_with_config("Quarter Triple 4", &cfg).unwrap();
+        assert!(matches!(&second[..], [OutputItem::Misc(v)] if v.show() == "3"));
+        assert!(run_uiua_with_config("Secret", &cfg).is_err());
+    }
+
+    #[test]
+    fn unclosed_delimiters_explained() {
+        assert_eq!(
+            run_uiua("1\n[1 2 3\n+").unwrap_err(),
+            "unclosed `[` started at line 2 — did you forget a `]`?"
+        );
+        assert_eq!(
+            run_uiua("{1 [2] (3").unwrap_err(),
+            "unclosed `(` started at line 1 — did you forget a `)`?"
+        );
+        assert_eq!(
+            run_uiua("⇌ 1\n&p \"hello").unwrap_err(),
+            "unclosed `\"` started at line 2 — did you forget a `\"`?"
+        );
+    }
+
+    #[test]
+    fn same_behavior_same_fingerprint() {
+        let cfg = RunConfig::default();
+        let probes = [
+            eval_uiua("3", &cfg).unwrap(),
+            eval_uiua("[1 5 2]", &cfg).unwrap(),
+        ];
+        let fingerprint = |code| behavior_fingerprint(code, &probes).unwrap();
+        let doubled = fingerprint("×2");
+        assert_eq!(doubled, fingerprint("Double ← +.\nDouble"));
+        assert_eq!(doubled, fingerprint("add dup # same thing"));
+        assert_ne!(doubled, fingerprint("×3"));
+        assert_ne!(doubled, behavior_fingerprint("×2", &probes[..1]).unwrap());
+        assert!(behavior_fingerprint("⍤\"no\" 0", &probes).is_err());
+    }
+
+    #[test]
+    fn fingerprints_are_stable() {
+        let mut hash = Fnv1a::default();
+        hash.write(b"a");
+        assert_eq!(hash.0, 0xaf63_dc4c_8601_ec8c);
+
+        let cfg = RunConfig::default();
+        let probes = [
+            eval_uiua("3", &cfg).unwrap(),
+            eval_uiua("[1 5 2]", &cfg).unwrap(),
+        ];
+        // Changes only if how values are shown changes
+        assert_eq!(
+            behavior_fingerprint("×2", &probes).unwrap(),
+            0x9ff1_90f2_1c55_89f8
+        );
+    }
+
+    #[test]
+    fn truncation_keeps_the_configured_end() {
+        // 15 is pushed first, so it's at the bottom
+        let code = "1 2 3 4 5 6 7 8 9 10 11 12 13 14 15";
+        let shown = |outputs: &[OutputItem]| -> Vec<String> {
+            (outputs.iter())
+                .filter_map(|item| match item {
+                    OutputItem::Misc(value) => Some(value.show()),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let bottom = run_uiua(code).unwrap();
+        assert_eq!(
+            shown(&bottom),
+            ["15", "14", "13", "12", "11", "10", "9", "8", "7", "6"]
+        );
+        assert!(matches!(
+            bottom.last(),
+            Some(OutputItem::Continuation(5, StackEnd::Top))
+        ));
+
+        let cfg = RunConfig {
+            truncate_keep: TruncateKeep::Top,
+            ..Default::default()
+        };
+        let top = run_uiua_with_config(code, &cfg).unwrap();
+        assert_eq!(
+            shown(&top),
+            ["10", "9", "8", "7", "6", "5", "4", "3", "2", "1"]
+        );
+        assert!(matches!(
+            top.last(),
+            Some(OutputItem::Continuation(5, StackEnd::Bottom))
+        ));
+        assert_eq!(
+            render_item_text(top.last().unwrap(), &cfg).unwrap(),
+            "<5 more items at the bottom of the stack>\n"
+        );
+    }
+
+    #[test]
+    fn assertion_failures_are_reported_as_such() {
+        assert_eq!(
+            run_uiua(r#"⍤"boom" 0"#).unwrap_err(),
+            "assertion failed: boom"
+        );
+        assert!(run_uiua("+ @a @b")
+            .unwrap_err()
+            .starts_with("Error while running"));
+    }
+
+    #[test]
+    fn thrown_errors_stay_errors() {
+        let returned = run_uiua("\"error\"");
+        assert!(!is_error_result(&returned));
+        assert!(matches!(&returned.unwrap()[..], [OutputItem::Misc(v)] if v.show() == "\"error\""));
+
+        let thrown = run_uiua("⍤\"error\" 0");
+        assert!(is_error_result(&thrown));
+        assert!(thrown.unwrap_err().contains("error"));
+        assert!(is_error_result(&run_uiua("⍤\"oops\" 0 \"partial\"")));
+    }
+
+    #[test]
+    fn animations_capped() {
+        let animation = eval_uiua("÷500 ⊞+ ⇡500 ↯10_10 0", &RunConfig::default())
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(animation.shape().dims(), [500, 10, 10]);
+        let (capped, note) = cap_animation_frames(animation.clone(), 60);
+        assert_eq!(capped.shape().dims(), [60, 10, 10]);
+        assert_eq!(note.as_deref(), Some("(truncated to 60 frames)"));
+        assert!(animation.rows().take(60).eq(capped.rows()));
+
+        let (short, note) = cap_animation_frames(animation.clone(), 500);
+        assert_eq!((short, note), (animation, None));
+    }
+
+    #[test]
+    fn gifs_keep_to_the_frame_cap() {
+        let cfg = RunConfig {
+            gif_output: true,
+            max_gif_frames: 60,
+            ..RunConfig::default()
+        };
+        let outputs = run_uiua_with_config("÷500 ⊞+ ⇡500 ↯30_30 0", &cfg).unwrap();
+        let [OutputItem::Gif(gif), OutputItem::Note(note)] = &outputs[..] else {
+            panic!("Expected a GIF and a note, got {outputs:?}");
+        };
+        assert_eq!(note, "(truncated to 60 frames)");
+        let (_, frames) = uiua::encode::gif_bytes_to_value(gif).unwrap();
+        assert_eq!(frames.row_count(), 60);
+
+        // Still images and animations without GIFs turned on are left alone
+        assert!(matches!(
+            &run_uiua_with_config("÷99⊞+.⇡50", &cfg).unwrap()[..],
+            [OutputItem::Image(_)]
+        ));
+        let outputs = run_uiua("÷500 ⊞+ ⇡500 ↯30_30 0").unwrap();
+        assert!(matches!(&outputs[..], [OutputItem::Misc(_)]), "{outputs:?}");
+    }
+
+    #[test]
+    fn live_examples_run() {
+        let docs = docs_with_live_examples("add").unwrap();
+        assert!(docs.contains("+1 2\n# docs: 3\n# bot: 3"), "{docs}");
+        let examples = docs.split("\n\n").skip(1).count();
+        assert!((1..=MAX_LIVE_EXAMPLES).contains(&examples));
+        assert!(docs_with_live_examples("notaprimitive").is_err());
+    }
+
+    #[test]
+    fn small_top_value_forced_to_render() {
+        let code = "÷9⊞+.⇡10 ÷9⊞+.⇡10";
+        let cfg = RunConfig {
+            force_render_top_media: true,
+            ..RunConfig::default()
+        };
+        assert!(matches!(
+            &run_uiua(code).unwrap()[..],
+            [OutputItem::Misc(_), OutputItem::Misc(_)]
+        ));
+        assert!(matches!(
+            &run_uiua_with_config(code, &cfg).unwrap()[..],
+            [OutputItem::Misc(_), OutputItem::Image(_)]
+        ));
+    }
+
+    #[test]
+    fn docs_come_with_a_demo() {
+        let (docs, demo) = docs_and_demo("add").unwrap();
+        assert_eq!(docs, get_docs("add"));
+        assert!(matches!(&demo[..], [OutputItem::Misc(v)] if v.show() == "3"));
+
+        let (_, demo) = docs_and_demo("assert").unwrap();
+        assert!(matches!(&demo[..], [OutputItem::Note(_)]));
+        assert!(docs_and_demo("notaprimitive").is_err());
+    }
+
+    #[test]
+    fn matrices_drawn_as_heatmaps() {
+        let cfg = RunConfig {
+            heatmap: true,
+            ..RunConfig::default()
+        };
+        let gradient = "÷18⊞+.⇡10";
+        assert!(matches!(
+            &run_uiua(gradient).unwrap()[..],
+            [OutputItem::Misc(_)]
+        ));
+        let [OutputItem::Image(png)] = &run_uiua_with_config(gradient, &cfg).unwrap()[..] else {
+            panic!("expected a heatmap");
+        };
+        let heatmap = image::load_from_memory(png).unwrap().to_rgba8();
+        assert!(heatmap.width() >= MIN_AUTO_IMAGE_DIM as u32);
+        assert_eq!(heatmap.width(), heatmap.height());
+        let (lowest, highest) = (
+            heatmap.get_pixel(0, 0),
+            heatmap.get_pixel(heatmap.width() - 1, 0),
+        );
+        assert_eq!(lowest.0, [68, 1, 84, 255]);
+        assert_ne!(lowest, highest);
+        assert!(heatmap.pixels().any(|p| p[0] != p[1] || p[1] != p[2]));
+
+        let text = eval_uiua("\"ab\"", &cfg).unwrap().pop().unwrap();
+        assert!(render_heatmap(&text).is_none());
+    }
+
+    #[test]
+    fn short_descriptions_are_plain() {
+        let short = primitive_short("+").unwrap();
+        assert_eq!(short, "Add values");
+        assert_eq!(primitive_short("add"), Some(short));
+        let reverse = primitive_short("rev").unwrap();
+        assert!(!reverse.is_empty() && !reverse.contains('\n'));
+        assert!(!reverse.contains(['*', '_', '`', ':']), "{reverse}");
+        assert_eq!(primitive_short("notaprimitive"), None);
+    }
+
+    #[test]
+    fn experimental_primitives_listed() {
+        let listed = experimental_primitives();
+        assert!(!listed.is_empty());
+        assert!(listed.iter().all(|info| info.primitive.is_experimental()));
+        let sort = listed.iter().find(|info| info.name == "sort").unwrap();
+        assert_eq!(sort.glyph, Primitive::Sort.glyph());
+        assert_eq!(Some(&sort.short), primitive_short("sort").as_ref());
+    }
+
+    #[test]
+    fn disassembles_without_running() {
+        let listing = disassemble("+1 2").unwrap();
+        assert_eq!(
+            listing.lines().collect::<Vec<_>>()[..2],
+            ["Push 2", "Push 1"]
+        );
+        assert!(listing.contains("ADD"));
+        assert!(!listing.contains("SPANS"));
+        assert_eq!(disassemble("+1 2").unwrap(), listing);
+
+        // Compiled, but never run
+        assert!(disassemble("&p \"hi\"").unwrap().contains("print"));
+        assert!(disassemble("(+1").is_err());
+    }
+
+    #[test]
+    fn visible_source_is_what_the_user_wrote() {
+        for code in ["+1 2", "# Experimental!\n⊃⊙∘∘ 1 2", "", "  ⇡3\n"] {
+            assert_eq!(user_visible_source(&source_that_runs(code)), code);
+        }
+    }
+
+    #[test]
+    fn primitives_demoed_on_samples() {
+        let shown = |f| -> Vec<String> {
+            (demo_primitive(f).unwrap().iter())
+                .map(|item| match item {
+                    OutputItem::Misc(v) => v.show(),
+                    OutputItem::Note(note) => note.clone(),
+                    item => panic!("{item:?}"),
+                })
+                .collect()
+        };
+        let matrix = |code| eval_uiua(code, &RunConfig::default()).unwrap()[0].show();
+        assert_eq!(
+            shown("transpose"),
+            ["⍉ [1_2_3 4_5_6]".to_string(), matrix("[1_4 2_5 3_6]")]
+        );
+        assert_eq!(shown("+"), ["+ 3 [1 2 3 4]", "[4 5 6 7]"]);
+        assert_eq!(
+            shown("rotate"),
+            ["↻ 1 [1_2_3 4_5_6]".to_string(), matrix("[4_5_6 1_2_3]")]
+        );
+        assert_eq!(shown("reduce"), ["/+ [1 2 3 4]", "10"]);
+        assert_eq!(shown("pi")[0], "π");
+        assert!(demo_primitive("&p").is_err());
+    }
+
+    #[test]
+    fn only_top_value_shown() {
+        let cfg = RunConfig {
+            top_only: true,
+            ..RunConfig::default()
+        };
+        let code = "÷99⊞+.⇡50\n\"a\" \"b\"\n\"top\"";
+        let outputs = run_uiua_with_config(code, &cfg).unwrap();
+        assert!(matches!(&outputs[..], [OutputItem::Misc(v)] if v.show() == "\"top\""));
+
+        let cfg = RunConfig {
+            count_dropped: true,
+            ..cfg
+        };
+        let outputs = run_uiua_with_config(code, &cfg).unwrap();
+        assert!(matches!(
+            &outputs[..],
+            [
+                OutputItem::Misc(_),
+                OutputItem::Continuation(3, StackEnd::Bottom)
+            ]
+        ));
+    }
+
+    #[test]
+    fn deprecated_primitives_warned_about() {
+        assert_eq!(
+            deprecated_primitives_used("∊2 [1 2 3]\n∊3 [3]").unwrap(),
+            [Primitive::Member]
+        );
+        assert_eq!(deprecated_primitives_used("∈[1 2 3] 2").unwrap(), []);
+        assert!(deprecated_primitives_used("(∊").is_err());
+
+        assert!(matches!(
+            &run_uiua("∊2 [1 2 3]").unwrap()[..],
+            [OutputItem::Misc(_)]
+        ));
+        let cfg = RunConfig {
+            deprecation_warnings: true,
+            ..RunConfig::default()
+        };
+        let outputs = run_uiua_with_config("∊2 [1 2 3]", &cfg).unwrap();
+        assert!(matches!(
+            &outputs[..],
+            [OutputItem::Misc(_), OutputItem::Note(note)]
+                if note == "warning: `∊` member is deprecated"
+        ));
+    }
+
+    #[test]
+    fn describes_bindings() {
+        let code = "# Adds the two numbers then doubles\nF ← ×2+\nG ← 5";
+        assert_eq!(
+            describe_binding(code, "F").unwrap().as_deref(),
+            Some("F |2\nAdds the two numbers then doubles")
+        );
+        assert_eq!(
+            describe_binding(code, "G").unwrap().as_deref(),
+            Some("G |0")
+        );
+        assert_eq!(describe_binding(code, "H").unwrap(), None);
+        assert!(describe_binding("F ← +", "F").is_ok());
+        assert!(describe_binding("F ← (", "F").is_err());
+    }
+
+    #[test]
+    fn cheap_code_is_cheap() {
+        let cost = estimate_cost("+1 2").unwrap();
+        assert_eq!(cost.primitives, 1);
+        assert_eq!(cost.loops, 0);
+        assert!(cost.score < 10.0);
+    }
+
+    #[test]
+    fn big_ranges_and_loops_are_expensive() {
+        let range = estimate_cost("/+⇡1e9").unwrap();
+        assert_eq!(range.largest_literal, 1e9);
+        assert!(range.score > 1e9);
+        let nested = estimate_cost("⍥(⍥(+1)1000)1000 0").unwrap();
+        assert_eq!(nested.loops, 2);
+        assert!(nested.score > estimate_cost("⍥(+1)3 0").unwrap().score * 100.0);
+        assert!(estimate_cost("⍥(+1)∞ 0").unwrap().score.is_infinite());
+    }
+
+    #[test]
+    fn estimating_never_runs() {
+        // Would take far longer than the test if it were actually run
+        assert!(estimate_cost("⍥(+1)1e12 0").is_ok());
+    }
+
+    #[test]
+    fn small_images_can_be_upscaled() {
+        let cfg = RunConfig {
+            small_image_mode: SmallImageMode::Upscale,
+            ..RunConfig::default()
+        };
+        let items = run_uiua_with_config("÷18⊞+.⇡10", &cfg).unwrap();
+        let [OutputItem::Image(bytes)] = items.as_slice() else {
+            panic!("expected a single image, got {items:?}");
+        };
+        let image = image::load_from_memory(bytes).unwrap();
+        assert!(image.width() >= 30 && image.height() >= 30);
+        assert!(matches!(
+            run_uiua("÷18⊞+.⇡10").unwrap().as_slice(),
+            [OutputItem::Misc(_)]
+        ));
+    }
+
+    #[test]
+    fn small_images_can_be_drawn_as_text() {
+        let cfg = RunConfig {
+            small_image_mode: SmallImageMode::AsciiArt,
+            ..RunConfig::default()
+        };
+        let items = run_uiua_with_config("⊞=.⇡6", &cfg).unwrap();
+        let [OutputItem::Note(art)] = items.as_slice() else {
+            panic!("expected drawn text, got {items:?}");
+        };
+        assert_eq!(art.lines().next(), Some("@     "));
+        assert_eq!(art.lines().count(), 6);
+    }
+
+    #[test]
+    fn single_glyphs_win_and_longer_queries_are_names() {
+        assert_eq!(resolve_primitive("="), Some(Primitive::Eq));
+        assert_eq!(resolve_primitive("η"), Some(Primitive::Eta));
+        // Used to resolve to less than, by its first character
+        assert_eq!(resolve_primitive("<="), Some(Primitive::Le));
+        assert_eq!(resolve_primitive("+1"), None);
+        assert_eq!(resolve_primitive("pi"), Some(Primitive::Pi));
+    }
+
+    #[test]
+    fn names_resolve_ignoring_case() {
+        for query in ["reduce", "Reduce", "REDUCE", "red", "RED", "/"] {
+            assert_eq!(canonical_primitive_name(query), Some("reduce"), "{query}");
+        }
+        assert_eq!(canonical_primitive_name("PI"), Some("pi"));
+        assert_eq!(canonical_primitive_name("Nonsense"), None);
+        assert!(get_docs("REDUCE").contains("uiua.org/docs/reduce)"));
+    }
+
+    #[test]
+    fn blank_outputs_can_be_dropped() {
+        let cfg = RunConfig {
+            drop_blank_outputs: true,
+            label_stack_positions: true,
+            ..RunConfig::default()
+        };
+        let items = run_uiua_with_config("1 \"\" \"  \" 2", &cfg).unwrap();
+        let labels: Vec<_> = items
+            .iter()
+            .filter_map(|item| match item {
+                OutputItem::Label(label) => Some(label.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(labels, ["[3]", "[top]"]);
+        assert_eq!(run_uiua("\"\"").unwrap().len(), 1);
+
+        let items = run_uiua_with_config(&"\"\" 1 ".repeat(12), &cfg).unwrap();
+        assert!(matches!(
+            items.last(),
+            Some(OutputItem::Continuation(2, StackEnd::Top))
+        ));
+    }
+
+    #[test]
+    fn finds_examples_by_primitive_name() {
+        let examples = examples_mentioning("transpose");
+        assert!(!examples.is_empty());
+        assert!(examples.len() <= MAX_EXAMPLES);
+        assert!(examples.iter().all(|(_, code)| code.contains('⍉')));
+        assert!(examples
+            .iter()
+            .any(|(prim, _)| *prim == Primitive::Transpose));
+        assert!(examples_mentioning("").is_empty());
+        assert!(examples_mentioning("definitely not in any example").is_empty());
+    }
+
+    #[test]
+    fn minimal_formatting_keeps_layout() {
+        let code = "rev  range 5\n\n+1   <= 2 3";
+        assert_eq!(
+            format_code(code, FormatStyle::Minimal).unwrap(),
+            "⇌  ⇡ 5\n\n+1   ≤ 2 3"
+        );
+        assert_eq!(
+            format_code(code, FormatStyle::Canonical)
+                .unwrap()
+                .trim_end(),
+            "⇌ ⇡ 5\n\n+1 ≤ 2 3"
+        );
+        assert!(format_code("(", FormatStyle::Minimal).is_err());
+    }
+
+    #[test]
+    fn binary_input_is_rejected() {
+        let rejected = Some("input doesn't look like Uiua source.".to_string());
+        assert_eq!(run_uiua("\x01\x02\x03+1 2\x04\x05").err(), rejected);
+        assert_eq!(run_uiua("+1 2\0").err(), rejected);
+        assert_eq!(run_uiua("PNG\u{FFFD}\u{FFFD}").err(), rejected);
+        // A stray control character among normal code is still run
+        assert!(run_uiua("+1 2 # hello there\x07").is_ok());
+        assert!(run_uiua("+1\t2\r\n").is_ok());
+    }
+
+    #[test]
+    fn statements_run_as_cells() {
+        let cells = run_uiua_per_statement("F ← (\n  +1\n)\nF 1\n\"a\" \"b\"\nF").unwrap();
+        let sources: Vec<&str> = cells.iter().map(|(source, _)| source.as_str()).collect();
+        assert_eq!(sources, ["F ← (\n  +1\n)", "F 1", "\"a\" \"b\"", "F"]);
+        let counts: Vec<usize> = cells.iter().map(|(_, items)| items.len()).collect();
+        // The last one replaces the top string, which counts as a new value
+        assert_eq!(counts, [0, 1, 2, 1]);
+        assert!(run_uiua_per_statement("1\n+").is_err());
+    }
+
+    #[test]
+    fn dominant_kind_follows_priority() {
+        let kind = |code: &str| dominant_output_kind(&run_uiua(code).unwrap());
+        assert_eq!(kind("\"hi\" ÷99⊞+.⇡50 ∿×τ÷441⇡441"), OutputKind::Audio);
+        assert_eq!(kind("\"hi\" ÷99⊞+.⇡50"), OutputKind::Image);
+        assert_eq!(kind("\"hi\" 5"), OutputKind::Misc);
+        assert_eq!(dominant_output_kind(&[]), OutputKind::Misc);
+    }
+
+    #[test]
+    fn seeded_runs_repeat() {
+        let seeded = |seed| RunConfig {
+            seed: Some(seed),
+            ..RunConfig::default()
+        };
+        let code = "⍥⚂20 ⌊×100∵⋅⚂⇡10";
+        let first = eval_uiua(code, &seeded(7)).unwrap();
+        assert_eq!(first, eval_uiua(code, &seeded(7)).unwrap());
+        assert_ne!(first, eval_uiua(code, &seeded(8)).unwrap());
+    }
+
+    #[test]
+    fn pad_links_round_trip() {
+        let code = "⇌⇡5 # reversed\n\"ok\"";
+        let message = format_and_get_pad_link(code).unwrap();
+        let link = message
+            .strip_prefix("[pad](")
+            .and_then(|rest| rest.split_once(')'))
+            .unwrap()
+            .0;
+        assert_eq!(code_from_pad_link(link).unwrap(), code);
+        assert_eq!(
+            code_from_pad_link(&pad_link(code, PadLinkKind::Embed)).unwrap(),
+            code
+        );
+        // The pad writes links without the trailing padding
+        assert_eq!(
+            code_from_pad_link("https://uiua.org/pad?src=0_13_0-rc_4__4o2c4oqaCg").unwrap(),
+            "⍜⊚\n"
+        );
+    }
+
+    #[test]
+    fn malformed_pad_links_are_rejected() {
+        for link in [
+            "https://example.com/pad?src=0_13_0__MQ==",
+            "https://uiua.org/docs?src=0_13_0__MQ==",
+            "https://uiua.org/pad?code=MQ==",
+            "https://uiua.org/pad?src=MQ==",
+            "https://uiua.org/pad?src=0_13_0__!!!",
+        ] {
+            assert!(code_from_pad_link(link).is_err(), "{link}");
+        }
+    }
+
+    #[test]
+    fn pad_links_run() {
+        let link = pad_link("⇌\"olleh\"", PadLinkKind::Pad);
+        let outputs = run_pad_link(&link, &RunConfig::default()).unwrap();
+        assert!(matches!(&outputs[..], [OutputItem::Misc(v)] if v.show() == "\"hello\""));
+
+        let err = run_pad_link("https://uiua.org/pad?src=MQ==", &RunConfig::default()).unwrap_err();
+        assert!(err.starts_with("Couldn't read the pad link: "), "{err}");
+    }
+
+    #[test]
+    fn reports_experimental_features() {
+        assert_eq!(experimental_features_used("⍆ [3 1 2]").unwrap(), ["sort"]);
+        assert_eq!(
+            experimental_features_used("⊟₃ 1 2 3").unwrap(),
+            ["Subscripts"]
+        );
+        assert!(experimental_features_used("+1 2").unwrap().is_empty());
+        assert!(experimental_features_used("+").is_ok());
+        assert!(experimental_features_used("(").is_err());
+    }
+
+    #[test]
+    fn diff_marks_formatted_lines() {
+        let diff = format_inline_diff("x ← 5\nrev range x\n+1 2").unwrap();
+        assert_eq!(
+            diff,
+            "```diff\n  x ← 5\n- rev range x\n+ ⇌ ⇡ x\n  +1 2\n```"
+        );
+        assert_eq!(
+            format_inline_diff("⇌ ⇡ 5").unwrap(),
+            "No changes, the code is already formatted"
+        );
+        assert!(format_inline_diff("(").is_err());
+    }
+
+    #[test]
+    fn extra_channels_downmixed() {
+        let code = "⊞÷+1⇡6 ∿×τ×220÷44100⇡44100";
+        let outputs = run_uiua(code).unwrap();
+        let [OutputItem::Audio(ogg)] = &outputs[..] else {
+            panic!("{outputs:?}");
+        };
+        // The channel count comes right after the version in Vorbis's identification header
+        let header = ogg.windows(7).position(|w| w == b"\x01vorbis").unwrap();
+        assert_eq!(ogg[header + 11], 2);
+
+        let mixed = downmix(&[vec![1.0], vec![2.0], vec![3.0], vec![4.0], vec![5.0]], 2);
+        assert_eq!(mixed, [vec![3.0], vec![3.0]]);
+
+        let cfg = RunConfig {
+            channel_overflow: ChannelOverflow::Decline,
+            ..RunConfig::default()
+        };
+        let outputs = run_uiua_with_config(code, &cfg).unwrap();
+        assert!(matches!(
+            &outputs[..],
+            [OutputItem::Misc(_), OutputItem::Note(note)]
+                if note == "(not played, 6 channels is more than the 2 allowed)"
+        ));
+    }
+
+    #[test]
+    fn audio_info_measures_clips() {
+        let clip = eval_uiua("⊟.∿×τ×220÷44100⇡66150", &RunConfig::default())
+            .unwrap()
+            .pop()
+            .unwrap();
+        let info = audio_info(&clip).unwrap();
+        assert_eq!((info.channels, info.samples), (2, 66150));
+        assert_eq!(info.duration, Duration::from_millis(1500));
+        assert_eq!(info.to_string(), "🔊 1.5s stereo");
+        assert_eq!(audio_info(&uiua::Value::from("hi")), None);
+    }
+
+    #[test]
+    fn latex_mode_writes_lists_and_matrices() {
+        let cfg = RunConfig {
+            latex_output: true,
+            ..RunConfig::default()
+        };
+        let items = run_uiua_with_config("[[1 0] [0 1]]", &cfg).unwrap();
+        let text = render_item_text(&items[0], &cfg).unwrap();
+        assert!(text.starts_with("\\begin{bmatrix}"));
+        assert!(matches!(
+            run_uiua_with_config("÷99⊞+.⇡50", &cfg).unwrap().as_slice(),
+            [OutputItem::Image(_)]
+        ));
+    }
+
+    #[test]
+    fn glyphs_listed_once_in_order() {
+        assert_eq!(
+            glyphs_in_code("/+ ⇡10 \"⇌\" # ×\n/+ rev [1 2]"),
+            [
+                Primitive::Reduce,
+                Primitive::Add,
+                Primitive::Range,
+                Primitive::Reverse
+            ]
+        );
+        assert!(glyphs_in_code("1 2 \"abc\"").is_empty());
+    }
+
+    #[test]
+    fn audio_is_concatenated_with_gaps() {
+        let cfg = RunConfig::default();
+        let clips = eval_uiua("∿×τ×220÷44100⇡44100 ⊟.∿×τ×330÷44100⇡22050", &cfg).unwrap();
+        let clips: Vec<&uiua::Value> = clips.iter().collect();
+        let joined = concat_channels(&clips, Duration::from_millis(500), &cfg).unwrap();
+        assert_eq!(joined.len(), 2);
+        assert!(joined
+            .iter()
+            .all(|channel| channel.len() == 22050 + 22050 + 44100));
+        // The mono clip plays on both channels
+        assert_eq!(joined[0][66150..], joined[1][66150..]);
+        assert!(concat_audio(&clips, Duration::from_millis(500)).is_some());
+        assert!(concat_audio(&[&uiua::Value::from("hi")], Duration::ZERO).is_none());
+
+        let cfg = RunConfig {
+            concat_audio: true,
+            ..RunConfig::default()
+        };
+        let items = run_uiua_with_config("∿÷10⇡100 \"hi\" ∿÷10⇡100", &cfg).unwrap();
+        assert!(matches!(
+            items.as_slice(),
+            [OutputItem::Audio(_), OutputItem::Misc(_)]
+        ));
+    }
+
+    #[test]
+    fn slow_renders_time_out() {
+        let cfg = RunConfig {
+            render_timeout: Duration::from_millis(1),
+            ..RunConfig::default()
+        };
+        let items = run_uiua_with_config("÷2+1∿⊞×.⇡1500 5", &cfg).unwrap();
+        assert!(matches!(items[0], OutputItem::Misc(_)), "{items:?}");
+        assert!(matches!(&items[1], OutputItem::Note(note) if note == "render timed out"));
+    }
+
+    #[test]
+    fn parallel_renders_keep_order() {
+        let cfg = RunConfig {
+            render_threads: 2,
+            ..RunConfig::default()
+        };
+        let code = "\"text\" ÷99⊞+⇡40⇡30 ÷99⊞+⇡80⇡70 ÷99⊞+⇡50⇡60 ÷99⊞+⇡35⇡45 ÷99⊞+⇡90⇡100";
+        let described = render_accessible(&run_uiua_with_config(code, &cfg).unwrap());
+        let kinds: Vec<&str> = (described.lines())
+            .map(|line| line.split_once(". ").unwrap().1)
+            .collect();
+        assert_eq!(
+            kinds[..5],
+            [
+                "image 100×90",
+                "image 45×35",
+                "image 60×50",
+                "image 70×80",
+                "image 30×40"
+            ]
+        );
+        assert!(kinds[5].contains("text"));
+    }
+
+    #[test]
+    fn total_media_size_capped() {
+        let code = "\"after\" ÷2+1∿÷3⊞×.⇡100 ÷2+1∿÷4⊞×.⇡100 ÷2+1∿÷5⊞×.⇡100 ÷2+1∿÷6⊞×.⇡100";
+        let sizes: Vec<usize> = (run_uiua(code).unwrap().iter())
+            .filter_map(|item| match item {
+                OutputItem::Image(bytes) => Some(bytes.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sizes.len(), 4);
+
+        let cfg = RunConfig {
+            max_total_media_bytes: sizes[0] + sizes[1] + sizes[2] - 1,
+            ..RunConfig::default()
+        };
+        let items = run_uiua_with_config(code, &cfg).unwrap();
+        let [OutputItem::Image(_), OutputItem::Image(_), OutputItem::Note(note), OutputItem::Misc(after)] =
+            &items[..]
+        else {
+            panic!("unexpected outputs: {items:?}");
+        };
+        assert_eq!(note, "remaining outputs omitted (size limit)");
+        assert_eq!(after.show(), "\"after\"");
+    }
+
+    #[test]
+    fn traces_each_word() {
+        let trace = run_uiua_trace("1\n+1 ×2 3 # comment").unwrap();
+        let steps: Vec<(&str, Vec<String>)> = (trace.iter())
+            .map(|snapshot| {
+                let stack = snapshot.stack.iter().map(|v| v.show()).collect();
+                (&*snapshot.word, stack)
+            })
+            .collect();
+        let expected: [(&str, &[&str]); 6] = [
+            ("1", &["1"]),
+            ("3", &["1", "3"]),
+            ("2", &["1", "3", "2"]),
+            ("×", &["1", "6"]),
+            ("1", &["1", "6", "1"]),
+            ("+", &["1", "7"]),
+        ];
+        assert_eq!(steps.len(), expected.len(), "{steps:?}");
+        for ((word, stack), (expected_word, expected_stack)) in steps.iter().zip(expected) {
+            assert_eq!(*word, expected_word);
+            assert_eq!(stack, expected_stack, "after {word}");
+        }
+
+        assert_eq!(
+            run_uiua_trace("/+ ⇡4").unwrap().last().unwrap().stack[0].show(),
+            "6"
+        );
+        assert!(run_uiua_trace("+1").is_err());
+        assert!(run_uiua_trace("(+1").is_err());
+        let long = run_uiua_trace(&"1 2\n".repeat(MAX_TRACE_SNAPSHOTS)).unwrap();
+        assert_eq!(long.len(), MAX_TRACE_SNAPSHOTS);
+    }
+
+    #[test]
+    fn only_the_runaway_guard_reports_growth() {
+        let interrupted: UiuaError = UiuaErrorKind::Interrupted.into();
+        assert_eq!(
+            describe_error(&interrupted, Some(Interruption::RanAway)),
+            "output growing without bound."
+        );
+        assert!(describe_error(&interrupted, None).contains("interrupted"));
+    }
+
+    #[test]
+    #[cfg(feature = "counting-allocator")]
+    fn runaway_growth_stopped() {
+        let cfg = RunConfig {
+            runaway_guard: Some(16 * 1024 * 1024),
+            execution_limit: Duration::from_secs(20),
+            ..RunConfig::default()
+        };
+        let started = Instant::now();
+        let err = run_uiua_with_config("⍥(⊂.)∞ [1]", &cfg).unwrap_err();
+        assert_eq!(err, "output growing without bound.");
+        assert!(started.elapsed() < cfg.execution_limit);
+
+        // Only what the run itself allocates counts, not what other threads hold on to
+        let elsewhere = std::thread::spawn(|| vec![1u8; 64 * 1024 * 1024])
+            .join()
+            .unwrap();
+        assert!(run_uiua_with_config("/+⇡1000", &cfg).is_ok());
+        drop(elsewhere);
+    }
+
+    #[test]
+    fn underflow_is_explained() {
+        assert_eq!(
+            run_uiua("+1").unwrap_err(),
+            "`+` add needs 2 values, but the stack only had 1 value (at 1:1)"
+        );
+        // Points at what ran out inside the function, rather than where it was called
+        assert_eq!(
+            run_uiua("F ← ⊂\n1\nF").unwrap_err(),
+            "`⊂` join needs 2 values, but the stack only had 1 value (at 1:5)"
+        );
+        assert!(run_uiua("+").unwrap_err().contains("only had 0 values"));
+    }
+
+    #[test]
+    fn underflow_wording_matches_uiua() {
+        let Err(error) = Uiua::with_safe_sys().run_str("+1") else {
+            panic!("+1 ran");
+        };
+        let UiuaErrorKind::Run(message, _) = &error.kind else {
+            panic!("{error:?}");
+        };
+        assert_eq!(
+            message.value.strip_prefix(UNDERFLOW_PREFIX),
+            Some("2"),
+            "{message:?}"
+        );
+    }
 }